@@ -0,0 +1,115 @@
+use std::{collections::HashSet, hint::black_box};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use osh_oxy::{
+    arena::Arena,
+    config::Exclusions,
+    event::Event,
+    ui::{EventFilter, FilterContext, store::EventStore},
+};
+
+/// the `i`th event, spread over a few folders and sessions like a real history
+fn test_event(i: usize) -> Event {
+    #[allow(clippy::cast_possible_wrap)]
+    let endtime = i as i64;
+    Event {
+        timestamp_millis: endtime,
+        command: format!("cmd {}", i % 5000),
+        endtime,
+        exit_code: i16::from(i.is_multiple_of(7)),
+        folder: format!("/home/someone/project-{}", i % 50).into(),
+        machine: "laptop".into(),
+        session: format!("session-{}", i / 200).into(),
+        root: None,
+    }
+}
+
+/// `size` events, newest first
+fn create_test_events(size: usize) -> Vec<Event> {
+    (0..size).rev().map(test_event).collect()
+}
+
+/// the filtering done before the columnar store: scan all events per filter run
+fn scan(events: &[Event], filters: &HashSet<EventFilter>, context: &FilterContext) -> usize {
+    let mut seen = HashSet::new();
+    events
+        .iter()
+        .filter(|event| {
+            !filters.contains(&EventFilter::Duplicates) || seen.insert(event.command.as_str())
+        })
+        .filter(|event| {
+            !context.exclude.excludes(&event.command)
+                && filters.iter().all(|f| f.matches(event.borrowed(), context))
+        })
+        .count()
+}
+
+/// toggling ctrl-s, ctrl-f, ctrl-e and ctrl-u on and off again
+fn toggles() -> Vec<HashSet<EventFilter>> {
+    let mut filters = HashSet::new();
+    let mut steps = Vec::new();
+    for filter in [
+        EventFilter::SessionId,
+        EventFilter::Folder,
+        EventFilter::ExitCodeSuccess,
+        EventFilter::Duplicates,
+    ] {
+        filters.insert(filter);
+        steps.push(filters.clone());
+    }
+    for filter in [EventFilter::Folder, EventFilter::Duplicates] {
+        filters.remove(&filter);
+        steps.push(filters.clone());
+    }
+    steps
+}
+
+fn benchmark_filter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("toggle_filters");
+    let context = FilterContext {
+        folder: "/home/someone/project-7".to_string(),
+        session_id: Some("session-42".to_string()),
+        ..FilterContext::default()
+    };
+    let steps = toggles();
+
+    for size in [100_000, 1_000_000] {
+        let events = create_test_events(size);
+        group.bench_with_input(BenchmarkId::new("scan", size), &events, |b, events| {
+            b.iter(|| {
+                for filters in &steps {
+                    black_box(scan(events, filters, &context));
+                }
+            });
+        });
+
+        // live updates are kept in the arena like the watcher does, it has to outlive the store
+        let arena = Arena::new();
+        let mut store = EventStore::new(Exclusions::default());
+        store.extend(events.iter().map(Event::borrowed).collect());
+        group.bench_function(BenchmarkId::new("store", size), |b| {
+            b.iter(|| {
+                for filters in &steps {
+                    black_box(store.filtered(filters, &context).len());
+                }
+            });
+        });
+
+        // a command finishing while the TUI is open, with all filters cached
+        let mut next = size;
+        group.bench_function(BenchmarkId::new("live_update", size), |b| {
+            b.iter(|| {
+                store.extend(vec![arena.keep(test_event(next)).borrowed()]);
+                next += 1;
+                for filters in &steps {
+                    black_box(store.filtered(filters, &context).len());
+                }
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_filter);
+criterion_main!(benches);
@@ -1,10 +1,12 @@
 use std::{fs::File, hint::black_box, time::Duration};
 
-use criterion::{Criterion, criterion_group, criterion_main};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
 use osh_oxy::{
-    event::Event,
+    OshHome,
+    event::{Event, EventRef},
     formats::{Kind, rmp},
-    mmap, osh_files,
+    mmap::Mmap,
+    osh_files,
 };
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
@@ -13,23 +15,86 @@ fn benchmark_load_rmp(c: &mut Criterion) {
     let mut group = c.benchmark_group("load_osh_files");
     group.measurement_time(Duration::from_secs_f64(16.0));
 
-    let oshs = osh_files(&Kind::Rmp).expect("osh files should load");
+    let home = OshHome::new(vec![], None).expect("osh home");
+    let oshs = osh_files(&home, &Kind::Rmp).expect("osh files should load");
     let osh_files: Vec<File> = oshs
-        .iter()
+        .keys()
         .map(|o| File::open(o).expect("open file"))
         .collect();
-    let oshs_data: Vec<&[u8]> = osh_files.iter().map(mmap).collect();
-
-    group.bench_function("load_all_files", |_| {
-        let all_events = oshs_data
-            .par_iter()
-            .map(|d| rmp::load_osh_events(d).expect("load events"))
-            .collect::<Vec<Vec<Event>>>();
-        black_box(all_events);
+    let oshs_data: Vec<Mmap> = osh_files
+        .iter()
+        .map(|file| Mmap::new(file).expect("mmap file"))
+        .collect();
+
+    group.bench_function("load_all_files", |b| {
+        b.iter(|| {
+            oshs_data
+                .par_iter()
+                .map(|d| rmp::load_osh_events(d).expect("load events"))
+                .collect::<Vec<Vec<Event>>>()
+        });
     });
 
+    group.bench_function("load_all_files_borrowed", |b| {
+        b.iter(|| {
+            oshs_data
+                .par_iter()
+                .map(|d| rmp::load_event_refs(d).expect("load events"))
+                .collect::<Vec<Vec<EventRef>>>()
+        });
+    });
+
+    group.finish();
+}
+
+/// a single `.bosh` file with `size` events, like the `local.bosh` of a machine used for years
+#[allow(clippy::expect_used)]
+fn big_file(size: usize) -> tempfile::NamedTempFile {
+    let file = tempfile::NamedTempFile::new().expect("temp file");
+    let mut writer =
+        rmp::BinaryWriter::new(std::io::BufWriter::new(file.reopen().expect("reopen")));
+    for i in 0..size {
+        #[allow(clippy::cast_possible_wrap)]
+        let endtime = i as i64;
+        Event {
+            timestamp_millis: endtime,
+            command: format!("cargo test --workspace {}", i % 5000),
+            endtime,
+            exit_code: 0,
+            folder: format!("/home/someone/project-{}", i % 50).into(),
+            machine: "laptop".into(),
+            session: format!("session-{}", i / 200).into(),
+            root: None,
+        }
+        .write(&mut writer)
+        .expect("write event");
+    }
+    writer.flush().expect("flush");
+    file
+}
+
+#[allow(clippy::expect_used)]
+fn benchmark_single_big_file(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_single_big_file");
+    group.sample_size(20);
+
+    for size in [250_000, 1_000_000] {
+        let file = big_file(size);
+        let data = Mmap::new(file.as_file()).expect("mmap file");
+
+        group.bench_with_input(BenchmarkId::new("sequential", size), &data, |b, data| {
+            b.iter(|| black_box(rmp::load_event_refs(data).expect("load events")));
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", size), &data, |b, data| {
+            b.iter(|| black_box(rmp::load_event_refs_parallel(data).expect("load events")));
+        });
+        group.bench_with_input(BenchmarkId::new("split_only", size), &data, |b, data| {
+            b.iter(|| black_box(rmp::split_records(data, 64).expect("split records")));
+        });
+    }
+
     group.finish();
 }
 
-criterion_group!(benches, benchmark_load_rmp);
+criterion_group!(benches, benchmark_load_rmp, benchmark_single_big_file);
 criterion_main!(benches);
@@ -0,0 +1,46 @@
+//! Owner of the memory loaded events borrow from. [`crate::load_streaming`] keeps the memory maps
+//! and decompressed files in an [`Arena`] and hands out [`crate::event::EventRef`]s into them, so
+//! the TUI holds one borrowed view per event instead of an owned copy of its strings.
+use std::{
+    any::Any,
+    sync::{Mutex, PoisonError},
+};
+
+/// Values that stay at their address until the arena is dropped.
+#[derive(Default)]
+pub struct Arena {
+    kept: Mutex<Vec<Box<dyn Any + Send + Sync>>>,
+}
+
+impl Arena {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// move `value` into the arena and borrow it for as long as the arena lives
+    pub fn keep<T: Send + Sync + 'static>(&self, value: T) -> &T {
+        let boxed = Box::new(value);
+        let kept: *const T = &raw const *boxed;
+        self.kept
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(boxed);
+        // boxes are only dropped with the arena and moving one doesn't move its contents
+        unsafe { &*kept }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kept_values_stay_in_place() {
+        let arena = Arena::new();
+        let first = arena.keep(String::from("first"));
+        let kept: Vec<&usize> = (0..100).map(|i| arena.keep(i)).collect();
+        assert_eq!(first, "first");
+        assert!(kept.iter().enumerate().all(|(i, &&kept)| i == kept));
+    }
+}
@@ -0,0 +1,642 @@
+//! Cache of the merged history in `.cache/` of the first root, so a search doesn't decode and sort
+//! every history file again. The cache holds the deduplicated events of all files sorted by
+//! endtime, and per file the loaded length, modification time and a hash of the last loaded bytes.
+//!
+//! Bytes appended to a file since are decoded and added to the cache, any other change (a file
+//! shrank, was rewritten, removed or relabeled) makes it stale and it is rebuilt from the files.
+//! Tombstones are not applied to the cache, they are checked when loading. The events file is
+//! memory mapped and its events borrowed from the map, see [`Cached`].
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    LoadError,
+    event::{self, Event, EventRef},
+    formats::{Kind, rmp},
+    mmap::Mmap,
+    modified_millis,
+};
+
+/// name of the cache folder in the first root
+pub const DIR_NAME: &str = ".cache";
+/// sources and layout of the cache, replaced atomically
+const INDEX: &str = "index";
+/// the cached events, oldest first
+const EVENTS: &str = "events";
+/// held while the cache is read or written
+const LOCK: &str = "lock";
+/// bumped when the layout changes, older caches are rebuilt
+const VERSION: u32 = 1;
+/// number of bytes at the end of the loaded part of a file that is hashed
+const TAIL_LEN: usize = 4096;
+
+/// History files with the labels of their roots, as loaded by [`crate::load_streaming`].
+pub type Files = [(PathBuf, Arc<str>)];
+
+/// the cached events and the loaded bytes per rmp file
+type Loaded = (Cached, HashMap<PathBuf, u64>);
+
+/// A history file as it was loaded into the cache.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Source {
+    path: PathBuf,
+    label: String,
+    /// bytes loaded
+    len: u64,
+    modified: i64,
+    /// [`tail_hash`] of the loaded bytes
+    tail: u64,
+}
+
+impl Source {
+    fn new(path: &Path, label: &str, file: &File, data: &[u8]) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            label: label.to_string(),
+            len: data.len() as u64,
+            modified: modified_millis(file),
+            tail: tail_hash(data),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    version: u32,
+    sources: Vec<Source>,
+    /// root labels of the cached events, which store the position in here
+    labels: Vec<String>,
+    /// bytes of the events file that belong to the cache, the rest is left from an interrupted
+    /// write
+    events_len: u64,
+}
+
+/// The events file of a loaded cache, mapped. It stays valid when the cache is updated: appends
+/// don't change the mapped part and rebuilds replace the file.
+pub struct Cached {
+    data: Mmap,
+    /// see [`Index::events_len`]
+    len: usize,
+    labels: Vec<Arc<str>>,
+}
+
+impl Cached {
+    fn open(dir: &Path, index: &Index) -> anyhow::Result<Self> {
+        let data = Mmap::new(&File::open(dir.join(EVENTS))?)?;
+        let len = usize::try_from(index.events_len)?;
+        if data.len() < len {
+            anyhow::bail!("events are truncated");
+        }
+        let labels = index.labels.iter().map(|l| Arc::from(l.as_str())).collect();
+        Ok(Self { data, len, labels })
+    }
+
+    /// the cached events, oldest first
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if the events file is corrupt.
+    pub fn events(&self) -> anyhow::Result<Vec<EventRef<'_>>> {
+        rmp::load_records::<(u32, EventRef)>(&self.data[..self.len])?
+            .into_iter()
+            .map(|(label, mut event)| {
+                event.root = Some(
+                    self.labels
+                        .get(label as usize)
+                        .ok_or(anyhow!("unknown label {label}"))?,
+                );
+                Ok(event)
+            })
+            .collect()
+    }
+}
+
+/// hash of the last [`TAIL_LEN`] bytes of `data`, appending to a file keeps the hash of the part
+/// that was loaded before
+fn tail_hash(data: &[u8]) -> u64 {
+    let tail = data
+        .get(data.len().saturating_sub(TAIL_LEN)..)
+        .unwrap_or(data);
+    event::fnv1a(event::FNV_OFFSET, tail)
+}
+
+/// The on-disk cache of a history, see the [module docs](self).
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    #[must_use]
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// the cached events brought up to date with `files` and the loaded bytes per rmp file. New
+    /// events are added to the cache. Files that fail to load are passed to `report` and skipped.
+    /// `None` if there is no cache or it is stale.
+    pub fn load(&self, files: &Files, report: impl FnMut(LoadError)) -> Option<Loaded> {
+        self.try_load(files, report).ok().flatten()
+    }
+
+    fn try_load(
+        &self,
+        files: &Files,
+        mut report: impl FnMut(LoadError),
+    ) -> anyhow::Result<Option<Loaded>> {
+        let _lock = self.lock()?;
+        let Some(mut index) = self.read_index()? else {
+            return Ok(None);
+        };
+        let mut sources: HashMap<PathBuf, Source> = index
+            .sources
+            .drain(..)
+            .map(|source| (source.path.clone(), source))
+            .collect();
+        if sources
+            .keys()
+            .any(|path| !files.iter().any(|(file, _)| file == path))
+        {
+            return Ok(None);
+        }
+
+        let mut added = Vec::new();
+        for (path, label) in files {
+            let cached = sources.get(path);
+            if cached.is_some_and(|source| source.label != label.as_ref()) {
+                return Ok(None);
+            }
+            let opened = File::open(path).and_then(|file| Ok((Mmap::new(&file)?, file)));
+            let (data, file) = match opened {
+                Ok(opened) => opened,
+                Err(_) if cached.is_some() => return Ok(None),
+                Err(e) => {
+                    report(LoadError::new(path, e));
+                    continue;
+                }
+            };
+            let compressed = Kind::of(path) == Some(Kind::RmpZst);
+            #[expect(clippy::cast_possible_truncation, reason = "file is mapped")]
+            let from = match cached {
+                None => 0,
+                Some(source) if source.len == data.len() as u64 => {
+                    if source.modified != modified_millis(&file) || source.tail != tail_hash(&data)
+                    {
+                        return Ok(None);
+                    }
+                    source.len as usize
+                }
+                Some(source) if source.len < data.len() as u64 && !compressed => {
+                    let Some(loaded) = data.get(..source.len as usize) else {
+                        return Ok(None);
+                    };
+                    if tail_hash(loaded) != source.tail {
+                        return Ok(None);
+                    }
+                    loaded.len()
+                }
+                Some(_) => return Ok(None),
+            };
+
+            let decoded = match data.get(from..) {
+                Some([]) | None => Ok((vec![], 0)),
+                Some(_) if compressed => {
+                    rmp::load_compressed_events(&data).map(|events| (events, data.len()))
+                }
+                Some(appended) => rmp::load_complete_events(appended),
+            };
+            let (mut events, consumed) = match decoded {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    report(LoadError::new(path, e));
+                    continue;
+                }
+            };
+            for event in &mut events {
+                event.root = Some(Arc::clone(label));
+            }
+            added.extend(events);
+            let loaded = data.get(..from + consumed).unwrap_or(&data);
+            sources.insert(path.clone(), Source::new(path, label, &file, loaded));
+        }
+
+        let cached = Cached::open(&self.dir, &index)?;
+        let mut events = cached.events()?;
+        let added = deduplicated(&events, added);
+        index.sources = sources.into_values().collect();
+        index.sources.sort_by(|a, b| a.path.cmp(&b.path));
+        let appends = match (added.first(), events.last()) {
+            (Some(first), Some(newest)) => first.endtime >= newest.endtime,
+            _ => true,
+        };
+        if !added.is_empty() {
+            if appends {
+                self.append_events(&mut index, added.iter().map(Event::borrowed))?;
+            } else {
+                events.extend(added.iter().map(Event::borrowed));
+                // stable like sorting `Event`s
+                events.sort_by_key(|event| event.endtime);
+                self.write_events(&mut index, events)?;
+            }
+        }
+        self.write_index(&index)?;
+
+        let cached = if added.is_empty() {
+            cached
+        } else {
+            Cached::open(&self.dir, &index)?
+        };
+        Ok(Some((cached, offsets(&index.sources))))
+    }
+
+    /// replace the cache with `events` loaded from the first `offsets` bytes of `files`
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if the cache can't be written.
+    pub fn rebuild(
+        &self,
+        files: &Files,
+        offsets: &HashMap<PathBuf, u64>,
+        mut events: Vec<EventRef<'_>>,
+    ) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = self.lock()?;
+        let mut index = Index {
+            version: VERSION,
+            ..Index::default()
+        };
+        for (path, label) in files {
+            let Some(&len) = offsets.get(path) else {
+                continue;
+            };
+            let file = File::open(path)?;
+            let data = Mmap::new(&file)?;
+            #[expect(clippy::cast_possible_truncation, reason = "file is mapped")]
+            let Some(loaded) = data.get(..len as usize) else {
+                continue;
+            };
+            index.sources.push(Source::new(path, label, &file, loaded));
+        }
+        // stable like sorting `Event`s
+        events.sort_by_key(|event| event.endtime);
+        self.write_events(&mut index, events)?;
+        self.write_index(&index)
+    }
+
+    /// exclusive lock on the cache, released on drop
+    fn lock(&self) -> std::io::Result<File> {
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir.join(LOCK))?;
+        if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(lock)
+    }
+
+    fn read_index(&self) -> anyhow::Result<Option<Index>> {
+        let data = match std::fs::read(self.dir.join(INDEX)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let index: Index = rmp_serde::from_slice(&data)?;
+        Ok((index.version == VERSION).then_some(index))
+    }
+
+    fn write_index(&self, index: &Index) -> anyhow::Result<()> {
+        let temp = self.dir.join(format!(".{INDEX}.tmp"));
+        std::fs::write(&temp, rmp_serde::to_vec(index)?)?;
+        std::fs::rename(temp, self.dir.join(INDEX))?;
+        Ok(())
+    }
+
+    /// append `events` to the events file, dropping what an interrupted write left behind
+    fn append_events<'a>(
+        &self,
+        index: &mut Index,
+        events: impl IntoIterator<Item = EventRef<'a>>,
+    ) -> anyhow::Result<()> {
+        let file = OpenOptions::new().write(true).open(self.dir.join(EVENTS))?;
+        // other processes only read the events of an index up to its `events_len`, which is at
+        // most this one
+        if file.metadata()?.len() > index.events_len {
+            file.set_len(index.events_len)?;
+        }
+        let mut writer = BufWriter::new(&file);
+        std::io::Seek::seek(&mut writer, std::io::SeekFrom::End(0))?;
+        index.events_len += write_records(&mut writer, &mut index.labels, events)?;
+        writer.flush()?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// replace the events file with `events`, the index is removed until it is written again
+    fn write_events<'a>(
+        &self,
+        index: &mut Index,
+        events: impl IntoIterator<Item = EventRef<'a>>,
+    ) -> anyhow::Result<()> {
+        match std::fs::remove_file(self.dir.join(INDEX)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let temp = self.dir.join(format!(".{EVENTS}.tmp"));
+        let file = File::create(&temp)?;
+        let mut writer = BufWriter::new(&file);
+        index.labels.clear();
+        index.events_len = write_records(&mut writer, &mut index.labels, events)?;
+        writer.flush()?;
+        file.sync_all()?;
+        std::fs::rename(temp, self.dir.join(EVENTS))?;
+        Ok(())
+    }
+}
+
+/// write `events` as records with their label position in `labels` and return the written bytes
+fn write_records<'a, W: Write>(
+    writer: W,
+    labels: &mut Vec<String>,
+    events: impl IntoIterator<Item = EventRef<'a>>,
+) -> anyhow::Result<u64> {
+    let mut counting = Counting {
+        inner: writer,
+        len: 0,
+    };
+    let mut writer = rmp::BinaryWriter::new(&mut counting);
+    for event in events {
+        let label = event.root.map(AsRef::as_ref).unwrap_or_default();
+        let position = labels
+            .iter()
+            .position(|known| known == label)
+            .unwrap_or_else(|| {
+                labels.push(label.to_string());
+                labels.len() - 1
+            });
+        writer.write_record(&(u32::try_from(position)?, event))?;
+    }
+    writer.flush()?;
+    Ok(counting.len)
+}
+
+/// counts the bytes written through it
+struct Counting<W> {
+    inner: W,
+    len: u64,
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// `added` sorted and without events that are already `cached` (sorted) or copies of each other
+fn deduplicated(cached: &[EventRef], mut added: Vec<Event>) -> Vec<Event> {
+    added.sort();
+    // a copy starts when the added event did (see `EventRef::id`) and ends after that or, as the
+    // same record, when the added event ended: cached events that ended before both can't be one
+    let Some(oldest) = added
+        .iter()
+        .map(|event| event.timestamp_millis.min(event.endtime))
+        .min()
+    else {
+        return added;
+    };
+    let newer = cached.partition_point(|event| event.endtime < oldest);
+    let mut seen: HashSet<_> = cached
+        .get(newer..)
+        .unwrap_or_default()
+        .iter()
+        .map(EventRef::id)
+        .collect();
+    added.retain(|event| seen.insert(event.id()));
+    added
+}
+
+/// loaded bytes per rmp file, compressed files aren't appended to
+fn offsets(sources: &[Source]) -> HashMap<PathBuf, u64> {
+    sources
+        .iter()
+        .filter(|source| Kind::of(&source.path) == Some(Kind::Rmp))
+        .map(|source| (source.path.clone(), source.len))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::testing::{append, event, events, write_events};
+
+    /// a history in `dir` with files `a.bosh` and `b.bosh`
+    struct Fixture {
+        dir: TempDir,
+        files: Vec<(PathBuf, Arc<str>)>,
+        cache: Cache,
+    }
+
+    impl Fixture {
+        fn new(a: &[i64], b: &[i64]) -> Self {
+            let dir = TempDir::new().unwrap();
+            let files: Vec<(PathBuf, Arc<str>)> = ["a.bosh", "b.bosh"]
+                .into_iter()
+                .map(|name| (dir.path().join(name), Arc::from("local")))
+                .collect();
+            write_events(&files[0].0, a);
+            write_events(&files[1].0, b);
+            let cache = Cache::new(dir.path().join(DIR_NAME));
+            Self { dir, files, cache }
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.dir.path().join(name)
+        }
+
+        /// rebuild the cache from the complete files
+        fn rebuild(&self) {
+            let mut events = Vec::new();
+            let mut offsets = HashMap::new();
+            for (path, label) in &self.files {
+                let mut loaded = rmp::read_file(path).unwrap();
+                for event in &mut loaded {
+                    event.root = Some(Arc::clone(label));
+                }
+                events.extend(loaded);
+                offsets.insert(path.clone(), std::fs::metadata(path).unwrap().len());
+            }
+            let events = deduplicated(&[], events);
+            let events = events.iter().map(Event::borrowed).collect();
+            self.cache.rebuild(&self.files, &offsets, events).unwrap();
+        }
+
+        /// endtimes of the cached events, `None` if the cache is stale
+        fn load(&self) -> Option<Vec<i64>> {
+            let (cached, _) = self.cache.load(&self.files, |error| panic!("{error}"))?;
+            let events = cached.events().unwrap();
+            assert!(
+                events
+                    .iter()
+                    .all(|event| event.root.map(AsRef::as_ref) == Some("local"))
+            );
+            Some(events.iter().map(|event| event.endtime).collect())
+        }
+    }
+
+    #[test]
+    fn missing_cache_is_stale() {
+        let fixture = Fixture::new(&[1], &[2]);
+        assert_eq!(fixture.load(), None);
+    }
+
+    #[test]
+    fn rebuild_merges_sorted_and_deduplicated() {
+        let fixture = Fixture::new(&[1, 3, 2], &[2, 4]);
+        fixture.rebuild();
+        assert_eq!(fixture.load(), Some(vec![1, 2, 3, 4]));
+        // loading again doesn't change anything
+        assert_eq!(fixture.load(), Some(vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn appended_events_are_added() {
+        let fixture = Fixture::new(&[1], &[2]);
+        fixture.rebuild();
+        append(&fixture.files[0].0, &events(&[5]));
+        // a copy of a cached event and one that is older than the cache
+        append(&fixture.files[1].0, &events(&[1, 3]));
+
+        assert_eq!(fixture.load(), Some(vec![1, 2, 3, 5]));
+        assert_eq!(fixture.load(), Some(vec![1, 2, 3, 5]));
+        append(&fixture.files[1].0, &events(&[6]));
+        assert_eq!(fixture.load(), Some(vec![1, 2, 3, 5, 6]));
+
+        let (_, offsets) = fixture.cache.load(&fixture.files, |_| {}).unwrap();
+        let len = std::fs::metadata(&fixture.files[1].0).unwrap().len();
+        assert_eq!(offsets[&fixture.files[1].0], len);
+    }
+
+    #[test]
+    fn truncated_record_is_loaded_later() {
+        let fixture = Fixture::new(&[1], &[2]);
+        fixture.rebuild();
+        let path = &fixture.files[0].0;
+        let complete = std::fs::metadata(path).unwrap().len();
+        append(path, &events(&[3]));
+        // the shell is still writing the record
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+        file.set_len(std::fs::metadata(path).unwrap().len() - 2)
+            .unwrap();
+
+        assert_eq!(fixture.load(), Some(vec![1, 2]));
+        let (_, offsets) = fixture.cache.load(&fixture.files, |_| {}).unwrap();
+        assert_eq!(offsets[path], complete);
+    }
+
+    #[test]
+    fn shrunk_file_is_stale() {
+        let fixture = Fixture::new(&[1, 3], &[2]);
+        fixture.rebuild();
+        // e.g. pruned
+        write_events(&fixture.files[0].0, &[1]);
+        assert_eq!(fixture.load(), None);
+    }
+
+    #[test]
+    fn rewritten_file_is_stale() {
+        let fixture = Fixture::new(&[1, 3], &[2]);
+        fixture.rebuild();
+        // same length, different events
+        write_events(&fixture.files[0].0, &[1, 4]);
+        assert_eq!(fixture.load(), None);
+
+        fixture.rebuild();
+        // longer, but not by appending
+        write_events(&fixture.files[0].0, &[5, 3, 6]);
+        assert_eq!(fixture.load(), None);
+    }
+
+    #[test]
+    fn removed_or_relabeled_file_is_stale() {
+        let mut fixture = Fixture::new(&[1], &[2]);
+        fixture.rebuild();
+        fixture.files[1].1 = Arc::from("team");
+        assert_eq!(fixture.load(), None);
+
+        fixture.files.pop();
+        fixture.rebuild();
+        assert_eq!(fixture.load(), Some(vec![1]));
+        fixture.files.pop();
+        assert_eq!(fixture.load(), None);
+    }
+
+    #[test]
+    fn new_files_are_added() {
+        let mut fixture = Fixture::new(&[1], &[3]);
+        fixture.rebuild();
+        let path = fixture.path("c.bosh.zst");
+        write_events(&path, &[2, 4]);
+        fixture.files.push((path, Arc::from("local")));
+
+        assert_eq!(fixture.load(), Some(vec![1, 2, 3, 4]));
+        assert_eq!(fixture.load(), Some(vec![1, 2, 3, 4]));
+        let (_, offsets) = fixture.cache.load(&fixture.files, |_| {}).unwrap();
+        assert_eq!(offsets.len(), 2);
+    }
+
+    #[test]
+    fn loaded_events_outlive_updates() {
+        let fixture = Fixture::new(&[2], &[3]);
+        fixture.rebuild();
+        let (cached, _) = fixture.cache.load(&fixture.files, |_| {}).unwrap();
+        append(&fixture.files[0].0, &events(&[4]));
+        assert_eq!(fixture.load(), Some(vec![2, 3, 4]));
+        // older than the cache, the events file is replaced
+        append(&fixture.files[1].0, &events(&[1]));
+        assert_eq!(fixture.load(), Some(vec![1, 2, 3, 4]));
+
+        let endtimes: Vec<i64> = cached.events().unwrap().iter().map(|e| e.endtime).collect();
+        assert_eq!(endtimes, vec![2, 3]);
+    }
+
+    #[test]
+    fn interrupted_append_is_dropped() {
+        let fixture = Fixture::new(&[1], &[2]);
+        fixture.rebuild();
+        let cached = fixture.path(DIR_NAME).join(EVENTS);
+        let mut file = OpenOptions::new().append(true).open(&cached).unwrap();
+        file.write_all(b"garbage").unwrap();
+
+        assert_eq!(fixture.load(), Some(vec![1, 2]));
+        append(&fixture.files[0].0, &events(&[3]));
+        assert_eq!(fixture.load(), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn copies_ending_at_another_time_are_deduplicated() {
+        let cached = event("cmd", 7);
+        let mut copy = cached.clone();
+        copy.endtime = 10;
+        let added = deduplicated(&[cached.borrowed()], vec![copy, event("ls", 10)]);
+        let commands: Vec<&str> = added.iter().map(|event| event.command.as_str()).collect();
+        assert_eq!(commands, vec!["ls"]);
+    }
+}
@@ -1,4 +1,9 @@
 pub mod append_event;
+pub mod archive;
 pub mod cat;
+pub mod compact;
 pub mod convert;
+pub mod files;
+pub mod resolve_conflicts;
+pub mod scrub;
 pub mod search;
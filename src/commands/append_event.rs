@@ -1,44 +1,200 @@
-use anyhow::Context;
+use std::{borrow::Cow, path::Path, sync::LazyLock};
 
-use crate::{event::Event, formats::rmp::BinaryWriter};
+use anyhow::{anyhow, bail};
 
+use crate::{
+    config::Exclusions,
+    event::Event,
+    formats::rmp::{self, BinaryWriter},
+    redact::Redactor,
+};
+
+/// compiled once and only for commands that are recorded
+static REDACTOR: LazyLock<Redactor> = LazyLock::new(Redactor::new);
+
+/// smallest plausible timestamp in milliseconds (1973-03-03), smaller ones are likely seconds
+const MIN_MILLIS: i64 = 100_000_000_000;
+/// largest plausible timestamp in milliseconds (year 5138), larger ones are likely micro- or
+/// nanoseconds
+const MAX_MILLIS: i64 = 100_000_000_000_000;
+/// smallest plausible timestamp in seconds (1973-03-03)
+const MIN_SECONDS: i64 = MIN_MILLIS / 1000;
+
+/// parse a timestamp argument, with a clear error for seconds with a fraction (`date '+%s.%N'`)
+///
+/// # Errors
+///
+/// Returns an `Err` if `value` isn't an integer.
+pub fn parse_timestamp(value: &str) -> Result<i64, String> {
+    value.parse().map_err(|e| {
+        if value.contains('.') {
+            format!("{value} has a fraction, expected whole milliseconds")
+        } else {
+            format!("{value}: {e}")
+        }
+    })
+}
+
+/// `timestamp` in milliseconds, converted from seconds with `fix_units`
+fn millis(name: &str, timestamp: i64, fix_units: bool) -> anyhow::Result<i64> {
+    match timestamp {
+        MIN_MILLIS..MAX_MILLIS => Ok(timestamp),
+        MIN_SECONDS..MIN_MILLIS if fix_units => Ok(timestamp * 1000),
+        MIN_SECONDS..MIN_MILLIS => Err(anyhow!(
+            "{name} {timestamp} looks like seconds, expected milliseconds (convert with \
+             --fix-units)"
+        )),
+        MAX_MILLIS.. => Err(anyhow!(
+            "{name} {timestamp} is too large for milliseconds, micro- or nanoseconds?"
+        )),
+        _ => Err(anyhow!(
+            "{name} {timestamp} is not a timestamp in milliseconds"
+        )),
+    }
+}
+
+/// reject events a shell integration got wrong, with `fix_units` timestamps in seconds are
+/// converted to milliseconds instead
+///
 /// # Errors
 ///
-/// Returns an `Err` if no osh file can be created or exists and if writing to it fails.
+/// Returns an `Err` if the folder is empty, a timestamp isn't in milliseconds or the command ended
+/// before it started.
+pub fn validate(event: &mut Event, fix_units: bool) -> anyhow::Result<()> {
+    if event.folder.is_empty() {
+        bail!("folder is empty");
+    }
+    event.timestamp_millis = millis("starttime", event.timestamp_millis, fix_units)?;
+    event.endtime = millis("endtime", event.endtime, fix_units)?;
+    if event.endtime < event.timestamp_millis {
+        bail!(
+            "endtime {} is before starttime {}",
+            event.endtime,
+            event.timestamp_millis
+        );
+    }
+    Ok(())
+}
+
+/// append `event` to the history `file` after validating it (see [`validate`]), commands
+/// starting with a space or matching `exclude` are skipped without validation and secrets are
+/// redacted (see [`crate::redact`])
+///
+/// # Errors
+///
+/// Returns an `Err` if `event` is invalid, no osh file can be created or exists and if writing to
+/// it fails.
 pub fn invoke(
-    starttime: i64,
-    command: &str,
-    folder: &str,
-    endtime: i64,
-    exit_code: i16,
-    machine: &str,
-    session: &str,
+    file: &Path,
+    mut event: Event,
+    exclude: &Exclusions,
+    fix_units: bool,
 ) -> anyhow::Result<()> {
-    if command.starts_with(' ') {
+    // not recorded, so not validated either
+    if event.command.starts_with(' ') || exclude.excludes(&event.command) {
         return Ok(());
     }
+    validate(&mut event, fix_units)?;
+    if let (Cow::Owned(command), _) = REDACTOR.redact(&event.command) {
+        event.command = command;
+    }
 
-    let mut osh_file = home::home_dir().context("home dir has to exist")?;
-    osh_file.push(".osh/");
-    std::fs::create_dir_all(&osh_file)?;
-    osh_file.push("local.bosh");
-
-    let file = std::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(osh_file.as_path())?;
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // waits for rewrites of the file, see `rmp::rewrite_file`
+    let file = rmp::lock(file, std::fs::OpenOptions::new().create(true).append(true))?;
     let mut writer = BinaryWriter::new(file);
-    Event {
-        timestamp_millis: starttime,
-        command: command.to_string(),
-        endtime,
-        exit_code,
-        folder: folder.to_string(),
-        machine: machine.to_string(),
-        session: session.to_string(),
-    }
-    .write(&mut writer)?;
+    event.write(&mut writer)?;
     writer.flush()?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000_000;
+
+    fn event(starttime: i64, endtime: i64, folder: &str) -> Event {
+        Event {
+            timestamp_millis: starttime,
+            command: "ls".to_string(),
+            endtime,
+            exit_code: 0,
+            folder: folder.into(),
+            machine: "m".into(),
+            session: "s".into(),
+            root: None,
+        }
+    }
+
+    fn error(mut event: Event, fix_units: bool) -> String {
+        validate(&mut event, fix_units).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn valid_events_are_unchanged() {
+        let mut valid = event(NOW, NOW + 10, "/tmp");
+        validate(&mut valid, false).unwrap();
+        assert!(valid == event(NOW, NOW + 10, "/tmp"));
+        // a command can finish within the same millisecond
+        validate(&mut event(NOW, NOW, "/tmp"), false).unwrap();
+    }
+
+    #[test]
+    fn empty_folder_is_rejected() {
+        assert_eq!(error(event(NOW, NOW, ""), false), "folder is empty");
+    }
+
+    #[test]
+    fn endtime_before_starttime_is_rejected() {
+        assert_eq!(
+            error(event(NOW, NOW - 1, "/"), false),
+            format!("endtime {} is before starttime {NOW}", NOW - 1)
+        );
+    }
+
+    #[test]
+    fn seconds_are_rejected_unless_fixed() {
+        let seconds = NOW / 1000;
+        assert!(error(event(seconds, NOW, "/"), false).starts_with("starttime 1700000000 looks"));
+        assert!(error(event(NOW, seconds + 1, "/"), false).starts_with("endtime 1700000001 looks"));
+
+        let mut fixed = event(seconds, seconds + 2, "/");
+        validate(&mut fixed, true).unwrap();
+        assert_eq!((fixed.timestamp_millis, fixed.endtime), (NOW, NOW + 2000));
+        // milliseconds are kept, but the converted endtime has to be after it
+        assert!(error(event(NOW + 10, seconds, "/"), true).contains("is before starttime"));
+    }
+
+    #[test]
+    fn fractions_are_rejected() {
+        assert_eq!(parse_timestamp("1700000000000"), Ok(NOW));
+        assert_eq!(
+            parse_timestamp("1700000000.123456789"),
+            Err("1700000000.123456789 has a fraction, expected whole milliseconds".to_string())
+        );
+        assert!(parse_timestamp("now").is_err());
+    }
+
+    #[test]
+    fn skipped_commands_are_not_validated() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("local.bosh");
+        let mut skipped = event(0, -1, "");
+        skipped.command = " ls".to_string();
+        invoke(&file, skipped, &Exclusions::default(), false).unwrap();
+        assert!(!file.exists());
+        assert!(invoke(&file, event(0, -1, ""), &Exclusions::default(), false).is_err());
+    }
+
+    #[test]
+    fn other_units_are_rejected() {
+        let nanos = NOW * 1_000_000;
+        assert!(error(event(nanos, nanos, "/"), true).contains("too large"));
+        assert!(error(event(0, NOW, "/"), true).contains("not a timestamp"));
+        assert!(error(event(-NOW, NOW, "/"), true).contains("not a timestamp"));
+    }
+}
@@ -0,0 +1,211 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+
+use super::compact::compacted;
+use crate::{
+    OshHome,
+    config::{Age, Retention},
+    event::Event,
+    formats::{Kind, rmp},
+    own_files,
+};
+
+/// move the events of the history file at `path` that ended before `cutoff` to the history file
+/// `archive`, `dry_run` leaves both files alone. Returns the number of archived events.
+fn archive_file(path: &Path, archive: &Path, cutoff: i64, dry_run: bool) -> anyhow::Result<usize> {
+    if dry_run {
+        let old = rmp::read_file(path)?
+            .into_iter()
+            .filter(|event| event.endtime < cutoff)
+            .count();
+        return Ok(old);
+    }
+
+    let mut moved = 0;
+    rmp::rewrite_file(path, |events| {
+        let (old, kept): (Vec<Event>, Vec<Event>) =
+            events.into_iter().partition(|event| event.endtime < cutoff);
+        if old.is_empty() {
+            return Ok(None);
+        }
+        moved = old.len();
+        if let Some(parent) = archive.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // archive first, an interruption leaves copies that are merged on load
+        rmp::rewrite_file(archive, |mut archived| {
+            archived.extend(old);
+            Ok(Some(compacted(archived)))
+        })
+        .map_err(|e| anyhow::anyhow!("{}: {e}", archive.display()))?;
+        Ok(Some(kept))
+    })?;
+    Ok(moved)
+}
+
+/// move events that ended longer than `older_than` (or the configured age) ago out of the history
+/// files of this machine (see [`own_files`]) into the archive folder, keeping their path relative
+/// to the root and compressed if configured. `dry_run` only reports.
+///
+/// # Errors
+///
+/// Returns an `Err` if no age is given, discovery fails or any file couldn't be archived; the
+/// other files are archived regardless.
+pub fn invoke(
+    home: &OshHome,
+    retention: &Retention,
+    older_than: Option<Age>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let Some(age) = older_than.or(retention.older_than) else {
+        bail!("no age given, pass --older-than or set retention.older-than in the config");
+    };
+    let cutoff = chrono::Utc::now()
+        .timestamp_millis()
+        .saturating_sub(age.millis());
+    let archive = retention.archive_dir(home);
+    let canonical_archive = archive.canonicalize().ok();
+    let roots: Vec<PathBuf> = home
+        .roots
+        .iter()
+        .filter_map(|root| root.path.canonicalize().ok())
+        .collect();
+
+    let mut failed = 0;
+    for path in own_files(home, &Kind::Rmp)? {
+        if canonical_archive
+            .as_ref()
+            .is_some_and(|archive| path.starts_with(archive))
+        {
+            continue;
+        }
+        let relative = roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .or_else(|| path.file_name().map(Path::new))
+            .unwrap_or(&path);
+        let mut target = archive.join(relative);
+        if retention.compress {
+            target = Kind::RmpZst.with_extension(&target);
+        }
+        match archive_file(&path, &target, cutoff, dry_run) {
+            Ok(0) => {}
+            Ok(archived) => println!(
+                "{}: {archived} events archived to {}",
+                path.display(),
+                target.display()
+            ),
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("failed to archive {failed} files");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        Root, load_sorted,
+        testing::{endtimes, write_events},
+    };
+
+    #[test]
+    fn old_events_are_moved() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("host.bosh");
+        let archive = dir.path().join("archive/host.bosh");
+        write_events(&path, &[1, 5, 2, 6]);
+
+        assert_eq!(archive_file(&path, &archive, 5, true).unwrap(), 2);
+        assert!(!archive.exists());
+
+        assert_eq!(archive_file(&path, &archive, 5, false).unwrap(), 2);
+        assert_eq!(endtimes(&path), vec![5, 6]);
+        assert_eq!(endtimes(&archive), vec![1, 2]);
+
+        // later runs add to the archive
+        assert_eq!(archive_file(&path, &archive, 6, false).unwrap(), 1);
+        assert_eq!(endtimes(&path), vec![6]);
+        assert_eq!(endtimes(&archive), vec![1, 2, 5]);
+    }
+
+    #[test]
+    fn archive_keeps_relative_paths() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("active")).unwrap();
+        write_events(&dir.path().join("active/host.bosh"), &[1]);
+        let roots = vec![Root {
+            label: "local".to_string(),
+            path: dir.path().to_path_buf(),
+        }];
+        let home = OshHome::new(roots, None).unwrap();
+        let age: Age = "1d".parse().unwrap();
+
+        invoke(&home, &Retention::default(), Some(age), false).unwrap();
+        assert!(endtimes(&dir.path().join("active/host.bosh")).is_empty());
+        assert_eq!(
+            endtimes(&dir.path().join("archive/active/host.bosh")),
+            vec![1]
+        );
+        // archived files aren't archived again
+        invoke(&home, &Retention::default(), Some(age), false).unwrap();
+        assert_eq!(
+            endtimes(&dir.path().join("archive/active/host.bosh")),
+            vec![1]
+        );
+
+        assert!(invoke(&home, &Retention::default(), None, false).is_err());
+    }
+
+    #[test]
+    fn archive_leaves_other_roots_alone() {
+        let mine = TempDir::new().unwrap();
+        let team = TempDir::new().unwrap();
+        write_events(&mine.path().join("host.bosh"), &[1]);
+        write_events(&team.path().join("ci.bosh"), &[1]);
+        let roots = vec![
+            Root::from_path(mine.path().to_path_buf()),
+            Root::from_path(team.path().to_path_buf()),
+        ];
+        let home = OshHome::new(roots, None).unwrap();
+
+        invoke(
+            &home,
+            &Retention::default(),
+            Some("1d".parse().unwrap()),
+            false,
+        )
+        .unwrap();
+        assert_eq!(endtimes(&mine.path().join("archive/host.bosh")), vec![1]);
+        assert_eq!(endtimes(&team.path().join("ci.bosh")), vec![1]);
+        assert!(!team.path().join("archive").exists());
+    }
+
+    #[test]
+    fn archive_is_compressed() {
+        let dir = TempDir::new().unwrap();
+        write_events(&dir.path().join("host.bosh"), &[1]);
+        let roots = vec![Root {
+            label: "local".to_string(),
+            path: dir.path().to_path_buf(),
+        }];
+        let home = OshHome::new(roots, None).unwrap();
+        let retention = Retention {
+            compress: true,
+            ..Retention::default()
+        };
+
+        invoke(&home, &retention, Some("1d".parse().unwrap()), false).unwrap();
+        assert_eq!(endtimes(&dir.path().join("archive/host.bosh.zst")), vec![1]);
+        assert_eq!(load_sorted(&home).unwrap().len(), 1);
+    }
+}
@@ -1,24 +1,176 @@
-use std::io::Write;
+use std::{collections::HashSet, io::Write};
 
 use chrono::Utc;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-use crate::load_sorted;
+use crate::{
+    OshHome,
+    event::Event,
+    load_sorted_timed,
+    output::{self, OutputFormat, template::Template},
+    timings::Timings,
+    ui::{EventFilter, FilterContext},
+};
 
+/// template used without `--format`
+pub const DEFAULT_FORMAT: &str = "{ago} --- {command}";
+
+/// apply `filters` and exclusions to `events` (sorted newest first), keeping the newest of
+/// duplicated commands.
+fn filter_events<'a>(
+    events: &'a [Event],
+    filters: &HashSet<EventFilter>,
+    context: &FilterContext,
+) -> Vec<&'a Event> {
+    let mut seen = HashSet::new();
+    events
+        .iter()
+        .filter(|event| !context.exclude.excludes(&event.command))
+        .filter(|event| filters.iter().all(|f| f.matches(event.borrowed(), context)))
+        .filter(|event| {
+            !filters.contains(&EventFilter::Duplicates) || seen.insert(event.command.as_str())
+        })
+        .collect()
+}
+
+/// print all events, newest first, formatted with `format` (see [`Template`]) and terminated by
+/// `\n` or `\0` with `null`. If enabled, `timings` are printed to stderr at the end.
+///
 /// # Errors
 ///
-/// Returns an `Err` if loading or writing to stdout fails.
-pub fn invoke() -> anyhow::Result<()> {
+/// Returns an `Err` if `format` is invalid, or loading or writing to stdout fails.
+#[expect(clippy::implicit_hasher, reason = "just used in the CLI")]
+pub fn invoke(
+    home: &OshHome,
+    output: OutputFormat,
+    format: &str,
+    null: bool,
+    filters: &HashSet<EventFilter>,
+    context: &FilterContext,
+    timings: &Timings,
+) -> anyhow::Result<()> {
+    let template = Template::parse(format)?;
+    let events = load_sorted_timed(home, timings)?;
+    let events = timings.measure("filter", || filter_events(&events, filters, context));
+    timings.measure("write", || write(&events, output, &template, null))?;
+    if timings.is_enabled() {
+        eprint!("{timings}");
+    }
+    Ok(())
+}
+
+fn write(
+    events: &[&Event],
+    output: OutputFormat,
+    template: &Template,
+    null: bool,
+) -> anyhow::Result<()> {
+    if output != OutputFormat::Text {
+        return output::write_events(std::io::stdout().lock(), output, events.iter().copied());
+    }
+
     let f = timeago::Formatter::new();
     let now = Utc::now().timestamp_millis();
-    let formatted: String = load_sorted()?
+    let separator = if null { '\0' } else { '\n' };
+    let formatted: String = events
         .par_iter()
         .map(|item| {
-            let d = std::time::Duration::from_millis((now - item.endtime).cast_unsigned());
-            format!("{} --- {}\n", f.convert(d), item.command)
+            let mut line = template.render(item, now, &f);
+            line.push(separator);
+            line
         })
         .collect();
 
     std::io::stdout().write_all(formatted.as_bytes())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::config::Exclusions;
+
+    fn event(command: &str, folder: &str, exit_code: i16) -> Event {
+        Event {
+            timestamp_millis: 0,
+            command: command.to_string(),
+            endtime: 0,
+            exit_code,
+            folder: folder.into(),
+            machine: "m".into(),
+            session: "s".into(),
+            root: None,
+        }
+    }
+
+    #[test]
+    fn filter_folder_and_exit_code() {
+        let events = [
+            event("ls", "/a", 0),
+            event("ls", "/b", 0),
+            event("false", "/a", 1),
+        ];
+        let filters = HashSet::from([EventFilter::Folder, EventFilter::ExitCodeSuccess]);
+        let context = FilterContext {
+            folder: "/a".into(),
+            ..FilterContext::default()
+        };
+        let filtered = filter_events(&events, &filters, &context);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(&*filtered[0].folder, "/a");
+        assert_eq!(filtered[0].command, "ls");
+    }
+
+    #[test]
+    fn filter_duplicates_keeps_newest() {
+        let events = [
+            event("ls", "/new", 0),
+            event("pwd", "/", 0),
+            event("ls", "/old", 0),
+        ];
+        let filters = HashSet::from([EventFilter::Duplicates]);
+        let filtered = filter_events(&events, &filters, &FilterContext::default());
+        let folders: Vec<_> = filtered.iter().map(|e| &*e.folder).collect();
+        assert_eq!(folders, vec!["/new", "/"]);
+    }
+
+    #[test]
+    fn no_filters_keeps_everything() {
+        let events = [event("ls", "/", 0), event("ls", "/", 0)];
+        let filtered = filter_events(&events, &HashSet::new(), &FilterContext::default());
+        assert_eq!(filtered.len(), 2);
+    }
+
+    #[test]
+    fn filter_root_label() {
+        let mut team = event("make", "/", 0);
+        team.root = Some(Arc::from("team"));
+        let events = [event("ls", "/", 0), team];
+        let filters = HashSet::from([EventFilter::Root]);
+        let context = FilterContext {
+            root: Some("team".to_string()),
+            ..FilterContext::default()
+        };
+        let filtered = filter_events(&events, &filters, &context);
+        let commands: Vec<_> = filtered.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["make"]);
+    }
+
+    #[test]
+    fn excluded_commands_are_hidden() {
+        let events = [
+            event("ls", "/", 0),
+            event("cd /tmp", "/", 0),
+            event("make", "/", 0),
+        ];
+        let context = FilterContext {
+            exclude: Exclusions::new(vec!["ls".parse().unwrap(), "cd *".parse().unwrap()]),
+            ..FilterContext::default()
+        };
+        let filtered = filter_events(&events, &HashSet::new(), &context);
+        let commands: Vec<_> = filtered.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["make"]);
+    }
+}
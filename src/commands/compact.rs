@@ -0,0 +1,314 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+
+use crate::{
+    OshHome, discover_files,
+    event::Event,
+    formats::{Kind, rmp},
+    own_files,
+};
+
+/// size of a set of history files, `bytes` on disk
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Stats {
+    events: usize,
+    bytes: u64,
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} events, {} bytes", self.events, self.bytes)
+    }
+}
+
+/// read the history file at `path`, a missing file has no events
+fn read(path: &Path) -> anyhow::Result<(Vec<Event>, Stats)> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => {
+            let events = rmp::read_file(path)?;
+            let stats = Stats {
+                events: events.len(),
+                bytes: metadata.len(),
+            };
+            Ok((events, stats))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok((vec![], Stats::default())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// `events` without copies (see [`Event::id`]) and sorted by endtime, oldest first like appended
+pub(super) fn compacted(mut events: Vec<Event>) -> Vec<Event> {
+    let mut seen = HashSet::new();
+    events.retain(|event| seen.insert(event.id()));
+    events.sort();
+    events
+}
+
+/// replace the history file at `path` with the [`compacted`] events of it and `events`, `dry_run`
+/// only reports. Returns the stats of the result.
+fn rewrite_compacted(path: &Path, events: Vec<Event>, dry_run: bool) -> anyhow::Result<Stats> {
+    if dry_run {
+        let (current, _) = read(path)?;
+        let events = compacted(current.into_iter().chain(events).collect());
+        return Ok(Stats {
+            events: events.len(),
+            bytes: rmp::encoded_len(path, events)?,
+        });
+    }
+    let mut after = Stats::default();
+    rmp::rewrite_file(path, |current| {
+        let events = compacted(current.into_iter().chain(events).collect());
+        after.events = events.len();
+        Ok(Some(events))
+    })?;
+    after.bytes = std::fs::metadata(path)?.len();
+    Ok(after)
+}
+
+/// sort and dedupe the history file at `path` in place, `dry_run` only reports
+fn compact_file(path: &Path, dry_run: bool) -> anyhow::Result<(Stats, Stats)> {
+    let (events, before) = read(path)?;
+    let unique: HashSet<_> = events.iter().map(Event::id).collect();
+    if events.is_sorted() && unique.len() == events.len() {
+        return Ok((before, before));
+    }
+    // appended since it was read, compact what is there now
+    Ok((before, rewrite_compacted(path, vec![], dry_run)?))
+}
+
+/// merge the history files at `paths` and the archive at `into` into the archive and delete them,
+/// `dry_run` only reports
+fn merge_into(paths: &[PathBuf], into: &Path, dry_run: bool) -> anyhow::Result<(Stats, Stats)> {
+    let (_, mut before) = read(into)?;
+    let mut events = Vec::new();
+    let into_canonical = into.canonicalize().ok();
+    let paths: Vec<&PathBuf> = paths
+        .iter()
+        .filter(|path| into_canonical.is_none() || path.canonicalize().ok() != into_canonical)
+        .collect();
+    // held until the files are removed, appends wait and start new files
+    let mut locks = Vec::new();
+    for path in &paths {
+        let locked = rmp::lock(path, std::fs::OpenOptions::new().read(true))?;
+        let file_events = rmp::read_events(&locked, path)?;
+        before.events += file_events.len();
+        before.bytes += locked.metadata()?.len();
+        events.extend(file_events);
+        locks.push(locked);
+    }
+    let after = rewrite_compacted(into, events, dry_run)?;
+    if !dry_run {
+        for path in paths {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok((before, after))
+}
+
+/// the `.bosh` and `.bosh.zst` files at `paths`, folders are searched recursively. Without
+/// `paths` the history files of this machine (see [`own_files`]), except for `home.file` if they
+/// are merged `into` an archive: new events are appended to it.
+fn history_files(home: &OshHome, paths: Vec<PathBuf>, into: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let active = home.file.canonicalize().ok();
+    for kind in Kind::HISTORY {
+        if paths.is_empty() {
+            files.extend(
+                own_files(home, &kind)?
+                    .into_iter()
+                    .filter(|path| !into || Some(path) != active.as_ref()),
+            );
+        }
+        for path in paths.iter().filter(|path| path.is_dir()) {
+            files.extend(discover_files(path, &kind)?);
+        }
+    }
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// sort the history files at `paths` by endtime and drop copies of events, each file on its own or
+/// merged into the archive `into`, and report sizes before and after. `dry_run` only reports.
+///
+/// # Errors
+///
+/// Returns an `Err` if no files are found, merging fails or any file couldn't be compacted; the
+/// other files are compacted regardless.
+pub fn invoke(
+    home: &OshHome,
+    paths: Vec<PathBuf>,
+    into: Option<&Path>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let files = history_files(home, paths, into.is_some())?;
+    if files.is_empty() {
+        bail!("no history files to compact");
+    }
+
+    if let Some(into) = into {
+        let (before, after) = merge_into(&files, into, dry_run)?;
+        println!(
+            "{} files: {before} -> {}: {after}",
+            files.len(),
+            into.display()
+        );
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for path in files {
+        match compact_file(&path, dry_run) {
+            Ok((before, after)) => println!("{}: {before} -> {after}", path.display()),
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("failed to compact {failed} files");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::testing::{endtimes, write_events};
+
+    #[test]
+    fn compact_file_sorts_and_dedupes() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("host.bosh");
+        // a long running command was appended late, and one event twice
+        write_events(&path, &[1, 3, 2, 3]);
+        let size = std::fs::metadata(&path).unwrap().len();
+
+        let (before, after) = compact_file(&path, true).unwrap();
+        assert_eq!(
+            before,
+            Stats {
+                events: 4,
+                bytes: size
+            }
+        );
+        assert_eq!(after.events, 3);
+        assert_eq!(endtimes(&path), vec![1, 3, 2, 3]);
+
+        assert_eq!(compact_file(&path, false).unwrap(), (before, after));
+        assert_eq!(endtimes(&path), vec![1, 2, 3]);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), after.bytes);
+        // already compact
+        let (before, after) = compact_file(&path, false).unwrap();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn compact_file_sorts_without_duplicates() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("host.bosh");
+        write_events(&path, &[1, 3, 2]);
+        let (before, after) = compact_file(&path, false).unwrap();
+        assert_eq!(before, after);
+        assert_eq!(endtimes(&path), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn compressed_files_report_sizes_on_disk() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("host.bosh.zst");
+        write_events(&path, &[2, 1]);
+
+        let size = || std::fs::metadata(&path).unwrap().len();
+        let (before, after) = compact_file(&path, true).unwrap();
+        assert_eq!(
+            before,
+            Stats {
+                events: 2,
+                bytes: size()
+            }
+        );
+        assert_eq!(compact_file(&path, false).unwrap(), (before, after));
+        assert_eq!(endtimes(&path), vec![1, 2]);
+        assert_eq!(size(), after.bytes);
+
+        // sorted files without copies are left alone
+        let inode = std::fs::metadata(&path).unwrap().ino();
+        assert_eq!(compact_file(&path, false).unwrap(), (after, after));
+        assert_eq!(std::fs::metadata(&path).unwrap().ino(), inode);
+    }
+
+    #[test]
+    fn merge_into_archive() {
+        let dir = TempDir::new().unwrap();
+        let paths = vec![dir.path().join("a.bosh"), dir.path().join("b.bosh")];
+        let archive = dir.path().join("archive.bosh");
+        write_events(&paths[0], &[1, 4]);
+        write_events(&paths[1], &[2, 4]);
+        write_events(&archive, &[0]);
+
+        let (before, after) = merge_into(&paths, &archive, true).unwrap();
+        assert_eq!((before.events, after.events), (5, 4));
+        assert!(paths.iter().all(|path| path.exists()));
+
+        merge_into(&paths, &archive, false).unwrap();
+        assert_eq!(endtimes(&archive), vec![0, 1, 2, 4]);
+        assert!(paths.iter().all(|path| !path.exists()));
+    }
+
+    #[test]
+    fn merge_all_into_archive_keeps_active_and_shared_files() {
+        let mine = TempDir::new().unwrap();
+        let team = TempDir::new().unwrap();
+        let home = OshHome::new(
+            vec![
+                crate::Root::from_path(mine.path().to_path_buf()),
+                crate::Root::from_path(team.path().to_path_buf()),
+            ],
+            None,
+        )
+        .unwrap();
+        let old = mine.path().join("old.bosh");
+        let shared = team.path().join("ci.bosh");
+        write_events(&home.file, &[3]);
+        write_events(&old, &[1]);
+        write_events(&shared, &[2]);
+        let archive = mine.path().join("archive/all.bosh");
+        std::fs::create_dir(mine.path().join("archive")).unwrap();
+
+        invoke(&home, vec![], Some(&archive), false).unwrap();
+        assert_eq!(endtimes(&archive), vec![1]);
+        assert!(!old.exists());
+        assert_eq!(endtimes(&home.file), vec![3]);
+        assert_eq!(endtimes(&shared), vec![2]);
+    }
+
+    #[test]
+    fn merge_into_listed_archive_keeps_it() {
+        let dir = TempDir::new().unwrap();
+        let paths = vec![dir.path().join("a.bosh"), dir.path().join("archive.bosh")];
+        write_events(&paths[0], &[2]);
+        write_events(&paths[1], &[1]);
+
+        let (before, _) = merge_into(&paths, &paths[1], false).unwrap();
+        assert_eq!(before.events, 2);
+        assert_eq!(endtimes(&paths[1]), vec![1, 2]);
+        assert!(!paths[0].exists());
+    }
+}
@@ -1,35 +1,130 @@
-use std::fs::File;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
 
-use anyhow::Context;
+use anyhow::{Context, bail};
 
 use crate::{
+    OshHome,
     event::Event,
-    formats::{Kind, json_lines, rmp::BinaryWriter},
-    mmap, osh_files,
+    formats::{Kind, json_lines, rmp},
+    mmap::Mmap,
+    osh_files,
 };
 
-/// convert from json lins to rmp
+/// read the events of a history file in any format
+fn read(path: &Path) -> anyhow::Result<Vec<Event>> {
+    match Kind::of(path) {
+        Some(Kind::JsonLines) => {
+            let file = File::open(path)?;
+            let data = Mmap::new(&file)?;
+            let events = json_lines::load_osh_events(&data)
+                .context("Failed to load events from JSON lines file")?;
+            Ok(events.into_iter().map(Event::from).collect())
+        }
+        Some(Kind::Rmp | Kind::RmpZst) => Ok(rmp::read_file(path)?),
+        Some(Kind::Tombstones) | None => bail!("not a history file"),
+    }
+}
+
+/// convert the history file at `path` to `kind` next to it and return the new path. Binary files
+/// are replaced, json lines files are kept.
+fn convert(path: &Path, kind: &Kind) -> anyhow::Result<PathBuf> {
+    let output_path = kind.with_extension(path);
+    if output_path == path {
+        return Ok(output_path);
+    }
+    let binary = Kind::of(path) != Some(Kind::JsonLines);
+    // binary files are removed, appends wait until then and start a new file
+    let _locked = if binary {
+        Some(rmp::lock(path, std::fs::OpenOptions::new().read(true))?)
+    } else {
+        None
+    };
+    let events = read(path)?;
+    rmp::rewrite_file(&output_path, |_| Ok(Some(events))).context("Failed to write output file")?;
+    if binary {
+        std::fs::remove_file(path)?;
+    }
+    Ok(output_path)
+}
+
+/// convert the history files at `paths`, or all json lines files if there are none, to rmp,
+/// compressed with `compress`
 ///
 /// # Errors
 ///
-/// Returns an `Err` if any operation in the conversion process fails.
-pub fn invoke() -> anyhow::Result<()> {
-    for path in osh_files(&Kind::JsonLines)? {
-        let file = File::open(&path)?;
-        let data = mmap(&file);
-        let events = json_lines::load_osh_events(data)
-            .context("Failed to load events from JSON lines file")?;
-
-        let output_path = path.with_extension("bosh");
-
-        let file = std::fs::File::create(&output_path).context("Failed to create output file")?;
-        let mut writer = BinaryWriter::new(file);
-        for event in events {
-            Event::from(event)
-                .write(&mut writer)
-                .context("Failed to write event")?;
+/// Returns an `Err` if discovery fails or any file couldn't be converted; the other files are
+/// converted regardless.
+pub fn invoke(home: &OshHome, paths: Vec<PathBuf>, compress: bool) -> anyhow::Result<()> {
+    let kind = if compress { Kind::RmpZst } else { Kind::Rmp };
+    let paths = if paths.is_empty() {
+        osh_files(home, &Kind::JsonLines)?.into_keys().collect()
+    } else {
+        paths
+    };
+
+    let mut failed = 0;
+    for path in paths {
+        match convert(&path, &kind) {
+            Ok(output_path) => println!("{} -> {}", path.display(), output_path.display()),
+            Err(e) => {
+                eprintln!("{}: {e:#}", path.display());
+                failed += 1;
+            }
         }
     }
-
+    if failed > 0 {
+        bail!("failed to convert {failed} files");
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn events() -> Vec<Event> {
+        (0..3)
+            .map(|i| Event {
+                timestamp_millis: i,
+                command: format!("cmd {i}"),
+                endtime: i,
+                exit_code: 0,
+                folder: "/".into(),
+                machine: "m".into(),
+                session: "s".into(),
+                root: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compress_and_decompress() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("host.bosh");
+        rmp::rewrite_file(&path, |_| Ok(Some(events()))).unwrap();
+
+        let compressed = convert(&path, &Kind::RmpZst).unwrap();
+        assert_eq!(compressed, dir.path().join("host.bosh.zst"));
+        assert!(!path.exists());
+        assert!(rmp::read_file(&compressed).unwrap() == events());
+
+        assert_eq!(convert(&compressed, &Kind::Rmp).unwrap(), path);
+        assert!(!compressed.exists());
+        assert!(rmp::read_file(&path).unwrap() == events());
+        // already converted
+        assert_eq!(convert(&path, &Kind::Rmp).unwrap(), path);
+    }
+
+    #[test]
+    fn unknown_files_fail() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "").unwrap();
+        assert!(convert(&path, &Kind::Rmp).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::Write;
+
+use crate::{
+    Discovery, OshHome, discover,
+    formats::{Kind, rmp},
+};
+
+/// one line per history file: loaded files with root label, size, event count and the original of
+/// conflict copies, then the skipped ones with the reason
+fn write_report<W: Write>(mut writer: W, discovery: &Discovery) -> anyhow::Result<()> {
+    let mut files: Vec<_> = discovery.files.iter().collect();
+    files.sort();
+    for (path, label) in files {
+        let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+        let events = match rmp::read_file(path).map(|events| events.len()) {
+            Ok(1) => "1 event".to_string(),
+            Ok(events) => format!("{events} events"),
+            Err(e) => format!("failed to load: {e}"),
+        };
+        write!(
+            writer,
+            "{}\t{label}\t{size} bytes\t{events}",
+            path.display()
+        )?;
+        if let Some(original) = discovery.conflicts.get(path) {
+            write!(writer, "\tconflict copy of {}", original.display())?;
+        }
+        writeln!(writer)?;
+    }
+
+    let mut skipped: Vec<_> = discovery.skipped.iter().collect();
+    skipped.sort_by(|a, b| a.0.cmp(&b.0));
+    for (path, reason) in skipped {
+        writeln!(writer, "{}\tskipped: {reason}", path.display())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// list the history files that are loaded and the ones that are skipped
+///
+/// # Errors
+///
+/// Returns an `Err` if discovery or writing to stdout fails.
+pub fn invoke(home: &OshHome) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    for kind in Kind::HISTORY {
+        write_report(&mut stdout, &discover(home, &kind)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{Root, event::Event, formats::rmp::BinaryWriter};
+
+    #[test]
+    fn report_lists_loaded_and_skipped_files() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("archive")).unwrap();
+        std::fs::write(root.join(".oshignore"), "archive/\n").unwrap();
+        std::fs::write(root.join("archive/old.bosh"), b"").unwrap();
+        std::fs::write(root.join("broken.bosh"), b"broken").unwrap();
+        let mut writer = BinaryWriter::new(File::create(root.join("local.bosh")).unwrap());
+        Event {
+            timestamp_millis: 0,
+            command: "ls".to_string(),
+            endtime: 0,
+            exit_code: 0,
+            folder: "/".into(),
+            machine: "m".into(),
+            session: "s".into(),
+            root: None,
+        }
+        .write(&mut writer)
+        .unwrap();
+        writer.flush().unwrap();
+
+        let roots = vec![Root {
+            label: "mine".to_string(),
+            path: root.clone(),
+        }];
+        let home = OshHome::new(roots, None).unwrap();
+        let mut report = Vec::new();
+        write_report(&mut report, &discover(&home, &Kind::Rmp).unwrap()).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+
+        let size = std::fs::metadata(root.join("local.bosh")).unwrap().len();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(&format!(
+            "{}\tmine\t6 bytes\tfailed to load",
+            root.join("broken.bosh").display()
+        )));
+        assert_eq!(
+            lines[1],
+            format!(
+                "{}\tmine\t{size} bytes\t1 event",
+                root.join("local.bosh").display()
+            )
+        );
+        assert_eq!(
+            lines[2],
+            format!(
+                "{}\tskipped: ignored by \"archive/\" in .oshignore",
+                root.join("archive/old.bosh").display()
+            )
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::bail;
+
+use crate::{
+    OshHome, discover,
+    event::Event,
+    formats::{Kind, rmp},
+};
+
+/// add the events of the conflict copy at `copy` missing in `original` to it and delete the copy,
+/// `dry_run` leaves both files alone. Returns the number of events that were only in the copy.
+fn resolve(copy: &Path, original: &Path, dry_run: bool) -> anyhow::Result<usize> {
+    let copied = rmp::read_file(copy)?;
+    if dry_run {
+        let events = match rmp::read_file(original) {
+            Ok(events) => events,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => bail!("{}: {e}", original.display()),
+        };
+        return Ok(merge(events, copied).1);
+    }
+
+    let missing = !original.exists();
+    let mut merged = 0;
+    rmp::rewrite_file(original, |events| {
+        let (events, only_copied) = merge(events, copied);
+        merged = only_copied;
+        Ok((merged > 0 || missing).then_some(events))
+    })?;
+    std::fs::remove_file(copy)?;
+    Ok(merged)
+}
+
+/// `events` and the events of `copied` missing in them, and the number of those
+fn merge(mut events: Vec<Event>, copied: Vec<Event>) -> (Vec<Event>, usize) {
+    let mut seen: HashSet<_> = events.iter().map(Event::id).collect();
+    let before = events.len();
+    events.extend(copied.into_iter().filter(|event| seen.insert(event.id())));
+    let merged = events.len() - before;
+    (events, merged)
+}
+
+/// fold the conflict copies of sync tools (see [`crate::conflict_original`]) back into their
+/// original history files, `dry_run` only reports
+///
+/// # Errors
+///
+/// Returns an `Err` if discovery fails or any copy couldn't be resolved; the other copies are
+/// resolved regardless.
+pub fn invoke(home: &OshHome, dry_run: bool) -> anyhow::Result<()> {
+    let mut conflicts = Vec::new();
+    for kind in Kind::HISTORY {
+        conflicts.extend(discover(home, &kind)?.conflicts);
+    }
+    conflicts.sort();
+
+    let mut failed = 0;
+    for (copy, original) in conflicts {
+        match resolve(&copy, &original, dry_run) {
+            Ok(merged) => println!(
+                "{}: {merged} events merged into {}",
+                copy.display(),
+                original.display()
+            ),
+            Err(e) => {
+                eprintln!("{}: {e}", copy.display());
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("failed to resolve {failed} conflict copies");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        Root,
+        testing::{endtimes, write_events},
+    };
+
+    #[test]
+    fn copy_is_folded_into_original() {
+        let dir = TempDir::new().unwrap();
+        let original = dir.path().join("host.bosh");
+        let copy = dir
+            .path()
+            .join("host.sync-conflict-20261001-120000-ABCDEFG.bosh");
+        // both diverged after 2
+        write_events(&original, &[1, 2, 3]);
+        write_events(&copy, &[1, 2, 4, 5]);
+
+        assert_eq!(resolve(&copy, &original, true).unwrap(), 2);
+        assert!(copy.exists());
+        assert_eq!(endtimes(&original), vec![1, 2, 3]);
+
+        assert_eq!(resolve(&copy, &original, false).unwrap(), 2);
+        assert!(!copy.exists());
+        assert_eq!(endtimes(&original), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn missing_original_is_replaced_by_copy() {
+        let dir = TempDir::new().unwrap();
+        let original = dir.path().join("host.bosh");
+        let copy = dir.path().join("host (conflicted copy).bosh");
+        write_events(&copy, &[1, 2]);
+
+        assert_eq!(resolve(&copy, &original, false).unwrap(), 2);
+        assert!(!copy.exists());
+        assert_eq!(endtimes(&original), vec![1, 2]);
+    }
+
+    #[test]
+    fn compressed_copies_are_resolved() {
+        let dir = TempDir::new().unwrap();
+        let original = dir.path().join("host.bosh.zst");
+        let copy = dir
+            .path()
+            .join("host.sync-conflict-20261001-120000-ABCDEFG.bosh.zst");
+        write_events(&original, &[1, 2]);
+        write_events(&copy, &[1, 3]);
+        let home = OshHome::new(vec![Root::from_path(dir.path().to_path_buf())], None).unwrap();
+
+        invoke(&home, false).unwrap();
+        assert!(!copy.exists());
+        assert_eq!(endtimes(&original), vec![1, 2, 3]);
+        assert!(rmp::load_osh_events(&std::fs::read(&original).unwrap()).is_err());
+    }
+}
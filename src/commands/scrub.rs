@@ -0,0 +1,223 @@
+use std::{collections::BTreeMap, fmt::Display, path::Path};
+
+use anyhow::bail;
+
+use crate::{
+    OshHome,
+    event::Event,
+    formats::{Kind, rmp},
+    own_files,
+    redact::Redactor,
+    tombstone::{self, Tombstone, Tombstones},
+};
+
+/// redactions in one history file
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Scrubbed {
+    /// number of changed commands
+    commands: usize,
+    /// number of secrets per rule
+    findings: BTreeMap<&'static str, usize>,
+    /// tombstones for the unredacted events, see [`scrub`]
+    tombstones: Vec<Tombstone>,
+}
+
+impl Display for Scrubbed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let findings: Vec<String> = self
+            .findings
+            .iter()
+            .map(|(rule, count)| format!("{rule}: {count}"))
+            .collect();
+        write!(
+            f,
+            "{} commands redacted ({})",
+            self.commands,
+            findings.join(", ")
+        )
+    }
+}
+
+/// redact the commands of `events`. Redacting changes the [`Event::id`] of an event, so the
+/// unredacted one gets a tombstone: copies of it in other files no longer show up next to the
+/// redacted event (and with the secret). Redacted events that were already removed get a
+/// tombstone as well.
+fn scrub(events: &mut [Event], redactor: &Redactor, removed: &Tombstones) -> Scrubbed {
+    let mut scrubbed = Scrubbed::default();
+    for event in events {
+        let (command, findings) = redactor.redact(&event.command);
+        if findings.is_empty() {
+            continue;
+        }
+        let unredacted = Tombstone::from(&*event);
+        let was_removed = removed.covers(event.borrowed());
+        event.command = command.into_owned();
+        scrubbed.tombstones.push(unredacted);
+        if was_removed {
+            scrubbed.tombstones.push(Tombstone::from(&*event));
+        }
+        scrubbed.commands += 1;
+        for (rule, count) in findings {
+            *scrubbed.findings.entry(rule).or_default() += count;
+        }
+    }
+    scrubbed
+}
+
+/// redact the commands in the history file at `path`, rewriting it unless `dry_run`
+fn scrub_file(
+    path: &Path,
+    redactor: &Redactor,
+    removed: &Tombstones,
+    dry_run: bool,
+) -> anyhow::Result<Scrubbed> {
+    if dry_run {
+        return Ok(scrub(&mut rmp::read_file(path)?, redactor, removed));
+    }
+    let mut scrubbed = Scrubbed::default();
+    rmp::rewrite_file(path, |mut events| {
+        scrubbed = scrub(&mut events, redactor, removed);
+        Ok((scrubbed.commands > 0).then_some(events))
+    })?;
+    Ok(scrubbed)
+}
+
+/// redact secrets in the history files of this machine (see [`own_files`] and
+/// [`crate::redact`]), compressed ones stay compressed, and report what changed. The unredacted
+/// events are recorded as tombstones next to `home.file`, which hides their copies in shared
+/// roots. `dry_run` only reports
+///
+/// # Errors
+///
+/// Returns an `Err` if discovery fails or any file couldn't be scrubbed; the other files are
+/// scrubbed regardless.
+pub fn invoke(home: &OshHome, dry_run: bool) -> anyhow::Result<()> {
+    let redactor = Redactor::new();
+    let removed = Tombstones::load(home, |error| eprintln!("{error}"))?;
+    let mut paths = Vec::new();
+    for kind in Kind::HISTORY {
+        paths.extend(own_files(home, &kind)?);
+    }
+    paths.sort();
+
+    let tombstones = tombstone::sidecar(&home.file);
+    let mut failed = 0;
+    for path in paths {
+        let scrubbed = scrub_file(&path, &redactor, &removed, dry_run).and_then(|scrubbed| {
+            if !dry_run && !scrubbed.tombstones.is_empty() {
+                tombstone::append(&tombstones, scrubbed.tombstones.iter().cloned())?;
+            }
+            Ok(scrubbed)
+        });
+        match scrubbed {
+            Ok(scrubbed) if scrubbed.commands > 0 => println!("{}: {scrubbed}", path.display()),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("failed to scrub {failed} files");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::testing::{commands, event, write};
+
+    /// replace `path` with events of `commands`
+    fn write_commands(path: &Path, commands: &[&str]) {
+        write(path, commands.iter().map(|command| event(command, 0)));
+    }
+
+    #[test]
+    fn scrub_rewrites_secrets() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("local.bosh");
+        write_commands(
+            &path,
+            &["ls", "export TOKEN=abc SECRET=def", "PASSWORD=x make"],
+        );
+
+        let scrubbed = scrub_file(&path, &Redactor::new(), &Tombstones::default(), false).unwrap();
+        assert_eq!(
+            scrubbed.to_string(),
+            "2 commands redacted (secret assignment: 3)"
+        );
+        assert_eq!(scrubbed.tombstones.len(), 2);
+        assert_eq!(
+            commands(&path),
+            vec![
+                "ls",
+                "export TOKEN=<redacted> SECRET=<redacted>",
+                "PASSWORD=<redacted> make"
+            ]
+        );
+        assert_eq!(
+            scrub_file(&path, &Redactor::new(), &Tombstones::default(), false).unwrap(),
+            Scrubbed::default()
+        );
+    }
+
+    #[test]
+    fn compressed_files_are_scrubbed() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("archive.bosh.zst");
+        write_commands(&path, &["export TOKEN=abc"]);
+        let home =
+            OshHome::new(vec![crate::Root::from_path(dir.path().to_path_buf())], None).unwrap();
+
+        invoke(&home, false).unwrap();
+        assert_eq!(commands(&path), vec!["export TOKEN=<redacted>"]);
+        assert!(rmp::load_osh_events(&std::fs::read(&path).unwrap()).is_err());
+    }
+
+    #[test]
+    fn unredacted_copies_stay_hidden() {
+        let mine = TempDir::new().unwrap();
+        let team = TempDir::new().unwrap();
+        let path = mine.path().join("local.bosh");
+        write(
+            &path,
+            [event("export TOKEN=abc", 1), event("export SECRET=x", 2)],
+        );
+        write(&team.path().join("ci.bosh"), [event("export TOKEN=abc", 1)]);
+        let roots = [mine.path(), team.path()].map(|root| crate::Root::from_path(root.into()));
+        let home = OshHome::new(roots.into(), None).unwrap();
+        tombstone::append(
+            &tombstone::sidecar(&home.file),
+            [Tombstone::from(&event("export SECRET=x", 2))],
+        )
+        .unwrap();
+
+        invoke(&home, false).unwrap();
+        // the copy in the shared root isn't touched but hidden, the removed event stays removed
+        assert_eq!(
+            commands(&team.path().join("ci.bosh")),
+            vec!["export TOKEN=abc"]
+        );
+        let loaded: Vec<String> = crate::load_sorted(&home)
+            .unwrap()
+            .into_iter()
+            .map(|event| event.command)
+            .collect();
+        assert_eq!(loaded, vec!["export TOKEN=<redacted>"]);
+    }
+
+    #[test]
+    fn dry_run_keeps_files() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("local.bosh");
+        write_commands(&path, &["export TOKEN=abc"]);
+
+        let scrubbed = scrub_file(&path, &Redactor::new(), &Tombstones::default(), true).unwrap();
+        assert_eq!(scrubbed.commands, 1);
+        assert_eq!(commands(&path), vec!["export TOKEN=abc"]);
+    }
+}
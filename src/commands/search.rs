@@ -1,39 +1,151 @@
-use std::{collections::HashSet, sync::Arc, thread};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    thread,
+};
 
 use crate::{
-    load_sorted,
-    ui::{EventFilter, Tui},
+    LoadError, OshHome,
+    arena::Arena,
+    event::{Event, EventId, EventRef},
+    formats::Kind,
+    load_streaming_timed,
+    output::{self, OutputFormat},
+    own_files, prune,
+    timings::Timings,
+    tombstone::{self, Tombstone},
+    ui::{EventFilter, FilterContext, Tui},
+    watch::HistoryWatcher,
 };
 
-/// # Panics
+/// list the files of this machine the `marked` events would be removed from on stderr, after
+/// confirmation on the terminal record tombstones for them next to `home.file` and rewrite the
+/// files, `dry_run` only lists
+fn prune_marked(home: &OshHome, marked: &[Event], dry_run: bool) -> anyhow::Result<()> {
+    let ids: HashSet<EventId> = marked.iter().map(Event::id).collect();
+    let removals = prune::plan(own_files(home, &Kind::Rmp)?, &ids)?;
+    let mut stderr = std::io::stderr().lock();
+    for removal in &removals {
+        let path = removal.path.display();
+        writeln!(stderr, "{path}: {} events", removal.events.len())?;
+        for event in &removal.events {
+            writeln!(stderr, "  {}", event.command)?;
+        }
+    }
+    if dry_run || removals.is_empty() {
+        return Ok(());
+    }
+
+    let total: usize = removals.iter().map(|removal| removal.events.len()).sum();
+    write!(
+        stderr,
+        "remove {total} events from {} files? [y/N] ",
+        removals.len()
+    )?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    BufReader::new(File::open("/dev/tty")?).read_line(&mut answer)?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        writeln!(stderr, "nothing removed")?;
+        return Ok(());
+    }
+
+    // copies on other machines and read-only files are covered by the tombstones
+    let tombstones = tombstone::sidecar(&home.file);
+    tombstone::append(&tombstones, marked.iter().map(Tombstone::from))?;
+    writeln!(
+        stderr,
+        "{}: recorded {} tombstones",
+        tombstones.display(),
+        marked.len()
+    )?;
+    for removal in &removals {
+        let path = removal.path.display();
+        match prune::remove_events(&removal.path, &ids) {
+            Ok(removed) => writeln!(stderr, "{path}: removed {removed} events")?,
+            Err(e) => writeln!(stderr, "{path}: {e}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Events marked for removal in the TUI are listed and removed from the history files after
+/// confirmation, or only listed with `dry_run`. If enabled, `timings` are printed to stderr after
+/// the TUI is closed.
 ///
-/// Panics if loading events or sending events through the channel fails.
+/// # Errors
+///
+/// Returns an `Err` if pruning or writing the selected event to stdout fails.
 #[expect(clippy::implicit_hasher, reason = "just used in the CLI")]
+#[expect(
+    clippy::too_many_arguments,
+    reason = "the options of the search command"
+)]
 pub fn invoke(
+    home: &OshHome,
     query: &str,
-    folder: &str,
-    session_id: Option<String>,
+    context: FilterContext,
     filters: HashSet<EventFilter>,
     show_score: bool,
-) {
+    output: OutputFormat,
+    dry_run: bool,
+    timings: &Timings,
+) -> anyhow::Result<()> {
     let (tx_item, receiver) = crossbeam_channel::unbounded();
-    thread::spawn(|| {
-        // TODO not sure if we want to sort already?
-        #[expect(clippy::expect_used, reason = "panic if loading fails")]
-        let _ = load_sorted()
-            .expect("osh files loading")
-            .into_iter()
-            .map(|item| {
-                tx_item
-                    .send(Arc::new(item))
-                    .expect("sending items through channel");
-            })
-            .collect::<Vec<_>>();
-
-        drop(tx_item);
+    let (tx_error, errors) = crossbeam_channel::unbounded();
+    let roots = home.labels();
+    let loader_home = home.clone();
+    let loader_timings = timings.clone();
+    // the TUI borrows the events until the process exits, the files are unmapped by the OS
+    let arena: &'static Arena = Box::leak(Box::default());
+    thread::spawn(move || {
+        let home = loader_home;
+        let root = home.roots.first().map_or(&home.file, |root| &root.path);
+        let report = |error: LoadError| {
+            let _ = tx_error.send(error);
+        };
+        let mut seen = HashSet::new();
+        let emit = |event: EventRef<'static>| {
+            seen.insert(event.id());
+            tx_item.send(event).is_ok()
+        };
+        let offsets = match load_streaming_timed(&home, arena, emit, report, &loader_timings) {
+            Ok(offsets) => offsets,
+            Err(e) => return report(LoadError::new(root, e)),
+        };
+
+        // keep following the history files for commands finishing while the picker is open
+        if let Err(e) = HistoryWatcher::new(&home, offsets, seen)
+            .and_then(|watcher| watcher.run(arena, &tx_item, &tx_error))
+        {
+            report(LoadError::new(root, e));
+        }
     });
 
-    if let Some(event) = Tui::start(receiver, query, folder, session_id, filters, show_score) {
-        println!("{}", event.command);
+    let outcome = Tui::start(
+        receiver,
+        errors,
+        query,
+        context,
+        filters,
+        show_score,
+        roots,
+        timings.clone(),
+    );
+    if timings.is_enabled() {
+        eprint!("{timings}");
     }
+    if !outcome.marked.is_empty() {
+        prune_marked(home, &outcome.marked, dry_run)?;
+    }
+    if let Some(event) = outcome.selected {
+        if output == OutputFormat::Text {
+            println!("{}", event.command);
+        } else {
+            output::write_event(std::io::stdout().lock(), output, &event)?;
+        }
+    }
+
+    Ok(())
 }
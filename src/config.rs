@@ -0,0 +1,303 @@
+//! Settings read from `config.toml` in the first history root, e.g.
+//!
+//! ```toml
+//! # never record or show these commands
+//! exclude = ["ls", "exit", "cd *", "/^git (status|diff)$/"]
+//!
+//! [retention]
+//! # `osh-oxy archive` moves older events to the archive folder below the first root
+//! older-than = "1y"
+//! archive = "archive"
+//! # write archives as `.bosh.zst`
+//! compress = true
+//! # whether search loads the archive, `--archives`/`--no-archives` override it
+//! search-archives = false
+//! ```
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, anyhow, bail};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::OshHome;
+
+/// name of the config file in the first history root
+pub const FILE_NAME: &str = "config.toml";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// commands that are neither recorded nor shown
+    #[serde(default)]
+    pub exclude: Exclusions,
+    #[serde(default)]
+    pub retention: Retention,
+}
+
+impl Config {
+    /// read the config file at `path`, a missing file is the default config
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if the file can't be read or is invalid.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let config = match std::fs::read_to_string(path) {
+            Ok(config) => config,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("{}", path.display())),
+        };
+        toml::from_str(&config).with_context(|| format!("{}", path.display()))
+    }
+}
+
+/// How old history is archived and whether it is searched.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Retention {
+    /// default age of events moved to the archive by `osh-oxy archive`
+    pub older_than: Option<Age>,
+    /// archive folder, relative to the first root
+    pub archive: PathBuf,
+    /// whether archived files are compressed (see [`crate::formats::Kind::RmpZst`])
+    pub compress: bool,
+    /// whether search loads archived history
+    pub search_archives: bool,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            older_than: None,
+            archive: PathBuf::from("archive"),
+            compress: false,
+            search_archives: true,
+        }
+    }
+}
+
+impl Retention {
+    /// the archive folder of `home`
+    #[must_use]
+    pub fn archive_dir(&self, home: &OshHome) -> PathBuf {
+        let root = home.roots.first().map_or(Path::new(""), |root| &root.path);
+        root.join(&self.archive)
+    }
+}
+
+/// An age like `30d`, `2w`, `6m` or `1y`; months have 30 days and years 365.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Age {
+    count: u32,
+    unit: char,
+}
+
+impl Age {
+    const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+    /// the age in milliseconds, `None` if it doesn't fit
+    fn checked_millis(count: u32, unit: char) -> Option<i64> {
+        let days = match unit {
+            'w' => 7,
+            'm' => 30,
+            'y' => 365,
+            _ => 1,
+        };
+        i64::from(count)
+            .checked_mul(days)?
+            .checked_mul(Self::DAY_MILLIS)
+    }
+
+    #[must_use]
+    pub fn millis(&self) -> i64 {
+        // parsing rejects ages that don't fit
+        Self::checked_millis(self.count, self.unit).unwrap_or(i64::MAX)
+    }
+}
+
+impl Display for Age {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.count, self.unit)
+    }
+}
+
+impl FromStr for Age {
+    type Err = anyhow::Error;
+    fn from_str(age: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow!("invalid age {age:?}, expected e.g. 30d, 2w, 6m or 1y");
+        let unit = age.chars().last().ok_or_else(invalid)?;
+        if !matches!(unit, 'd' | 'w' | 'm' | 'y') {
+            return Err(invalid());
+        }
+        let count = age[..age.len() - 1].parse().map_err(|_| invalid())?;
+        if Self::checked_millis(count, unit).is_none() {
+            bail!("age {age:?} is too large");
+        }
+        Ok(Self { count, unit })
+    }
+}
+
+impl<'de> Deserialize<'de> for Age {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let age = String::deserialize(deserializer)?;
+        age.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A pattern for commands: `/regex/`, a prefix ending in `*` like `cd *`, or an exact command.
+#[derive(Debug, Clone)]
+pub enum CommandPattern {
+    Exact(String),
+    Prefix(String),
+    Regex(Regex),
+}
+
+impl CommandPattern {
+    #[must_use]
+    pub fn matches(&self, command: &str) -> bool {
+        match self {
+            CommandPattern::Exact(exact) => command == exact,
+            CommandPattern::Prefix(prefix) => command.starts_with(prefix.as_str()),
+            CommandPattern::Regex(regex) => regex.is_match(command),
+        }
+    }
+}
+
+impl Display for CommandPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandPattern::Exact(exact) => write!(f, "{exact}"),
+            CommandPattern::Prefix(prefix) => write!(f, "{prefix}*"),
+            CommandPattern::Regex(regex) => write!(f, "/{regex}/"),
+        }
+    }
+}
+
+impl FromStr for CommandPattern {
+    type Err = regex::Error;
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        if let Some(regex) = pattern
+            .strip_prefix('/')
+            .and_then(|pattern| pattern.strip_suffix('/'))
+        {
+            Ok(CommandPattern::Regex(Regex::new(regex)?))
+        } else if let Some(prefix) = pattern.strip_suffix('*') {
+            Ok(CommandPattern::Prefix(prefix.to_string()))
+        } else {
+            Ok(CommandPattern::Exact(pattern.to_string()))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CommandPattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        pattern.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Commands that are never recorded, and hidden if they are already on disk.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Exclusions {
+    patterns: Vec<CommandPattern>,
+}
+
+impl Exclusions {
+    #[must_use]
+    pub fn new(patterns: Vec<CommandPattern>) -> Self {
+        Self { patterns }
+    }
+
+    /// whether any pattern matches `command`
+    #[must_use]
+    pub fn excludes(&self, command: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.matches(command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exclusions(patterns: &[&str]) -> Exclusions {
+        Exclusions::new(patterns.iter().map(|p| p.parse().unwrap()).collect())
+    }
+
+    #[test]
+    fn exact_prefix_and_regex() {
+        let exclude = exclusions(&["ls", "cd *", "/^git (status|diff)$/"]);
+        assert!(exclude.excludes("ls"));
+        assert!(!exclude.excludes("ls -la"));
+        assert!(exclude.excludes("cd /tmp"));
+        assert!(!exclude.excludes("cdk deploy"));
+        assert!(exclude.excludes("git status"));
+        assert!(!exclude.excludes("git status -s"));
+    }
+
+    #[test]
+    fn pattern_display_roundtrip() {
+        for pattern in ["ls", "cd *", "/^git/"] {
+            assert_eq!(
+                pattern.parse::<CommandPattern>().unwrap().to_string(),
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_regex_fails() {
+        assert!("/(/".parse::<CommandPattern>().is_err());
+        assert!(toml::from_str::<Config>(r#"exclude = ["/(/"]"#).is_err());
+    }
+
+    #[test]
+    fn ages() {
+        assert_eq!("30d".parse::<Age>().unwrap().millis(), 30 * Age::DAY_MILLIS);
+        assert_eq!("2w".parse::<Age>().unwrap().millis(), 14 * Age::DAY_MILLIS);
+        assert_eq!("1y".parse::<Age>().unwrap().millis(), 365 * Age::DAY_MILLIS);
+        assert_eq!("6m".parse::<Age>().unwrap().to_string(), "6m");
+        assert!(
+            "4000000000y"
+                .parse::<Age>()
+                .unwrap_err()
+                .to_string()
+                .contains("too large")
+        );
+        for invalid in ["", "y", "1", "1h", "-1d", "1.5y", "5000000000d"] {
+            assert!(invalid.parse::<Age>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn retention() {
+        assert_eq!(
+            toml::from_str::<Config>("").unwrap().retention,
+            Retention::default()
+        );
+        let config: Config =
+            toml::from_str("[retention]\nolder-than = \"1y\"\nsearch-archives = false\n").unwrap();
+        assert_eq!(config.retention.older_than, Some("1y".parse().unwrap()));
+        assert_eq!(config.retention.archive, Path::new("archive"));
+        assert!(!config.retention.search_archives);
+        assert!(toml::from_str::<Config>("[retention]\nolder-than = \"soon\"\n").is_err());
+    }
+
+    #[test]
+    fn load_config() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(FILE_NAME);
+        assert!(!Config::load(&path).unwrap().exclude.excludes("exit"));
+
+        std::fs::write(&path, "exclude = [\"exit\", \"cd *\"]\n").unwrap();
+        let config = Config::load(&path).unwrap();
+        assert!(config.exclude.excludes("exit"));
+        assert!(config.exclude.excludes("cd .."));
+
+        std::fs::write(&path, "unknown = 1\n").unwrap();
+        assert!(Config::load(&path).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::{io::Write, path::PathBuf};
+use std::{io::Write, path::PathBuf, sync::Arc};
 
 use arbitrary::Arbitrary;
 use serde::{Deserialize, Serialize};
@@ -7,6 +7,21 @@ use serde::{Deserialize, Serialize};
 use crate::formats::json_lines::JsonLineEvent;
 use crate::formats::rmp::BinaryWriter;
 
+/// offset basis of 64 bit FNV-1a
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// continue the 64 bit FNV-1a `hash` with `bytes`, stable across versions and machines unlike the
+/// std hashers
+pub(crate) fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Stable identity of an [`Event`], equal for copies of the same event in different files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EventId(u64);
+
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 /// Event format for entries in the history file.
@@ -17,11 +32,15 @@ pub struct Event {
     /// records time when the command ended (can be used to calculate duration)
     pub endtime: i64,
     pub exit_code: i16,
-    pub folder: String,
+    /// shared between events by [`crate::intern::Interner`]
+    pub folder: Arc<str>,
     /// a special machine id to filter by machine
-    pub machine: String,
+    pub machine: Arc<str>,
     /// a special session id to filter by session
-    pub session: String,
+    pub session: Arc<str>,
+    /// label of the history root the event was loaded from, not stored in history files
+    #[serde(skip)]
+    pub root: Option<Arc<str>>,
 }
 
 impl Arbitrary<'_> for Event {
@@ -35,8 +54,9 @@ impl Arbitrary<'_> for Event {
             endtime: u.arbitrary()?,
             exit_code: u.arbitrary()?,
             folder: folder.to_string_lossy().into(),
-            machine: machine_id,
-            session: session_id,
+            machine: machine_id.into(),
+            session: session_id.into(),
+            root: None,
         })
     }
 }
@@ -54,9 +74,10 @@ impl From<JsonLineEvent> for Event {
             command: event.command,
             endtime,
             exit_code: event.exit_code,
-            folder: event.folder,
-            machine: event.machine,
-            session: event.session,
+            folder: event.folder.into(),
+            machine: event.machine.into(),
+            session: event.session.into(),
+            root: None,
         }
     }
 }
@@ -82,6 +103,76 @@ impl Event {
     pub fn write<W: Write>(self, writer: &mut BinaryWriter<W>) -> anyhow::Result<()> {
         writer.write(self)
     }
+
+    /// identity of the event: hash of machine, session, start time and command
+    #[must_use]
+    pub fn id(&self) -> EventId {
+        self.borrowed().id()
+    }
+
+    /// view of the event that borrows its strings
+    #[must_use]
+    pub fn borrowed(&self) -> EventRef<'_> {
+        EventRef {
+            timestamp_millis: self.timestamp_millis,
+            command: &self.command,
+            endtime: self.endtime,
+            exit_code: self.exit_code,
+            folder: &self.folder,
+            machine: &self.machine,
+            session: &self.session,
+            root: self.root.as_ref(),
+        }
+    }
+}
+
+/// An [`Event`] that borrows its strings, e.g. decoded straight from a memory mapped history file
+/// with [`crate::formats::rmp::load_event_refs`]. Loading hands them out borrowed from the files it
+/// keeps in an [`crate::arena::Arena`], the matcher and the TUI work on them and only the selected
+/// event is converted into an owned [`Event`]. History files are only appended to or replaced by a
+/// rename, so the mapped contents don't change while they are borrowed.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct EventRef<'a> {
+    pub timestamp_millis: i64,
+    pub command: &'a str,
+    pub endtime: i64,
+    pub exit_code: i16,
+    pub folder: &'a str,
+    pub machine: &'a str,
+    pub session: &'a str,
+    /// see [`Event::root`]
+    #[serde(skip)]
+    pub root: Option<&'a Arc<str>>,
+}
+
+impl EventRef<'_> {
+    /// see [`Event::id`]
+    #[must_use]
+    pub fn id(&self) -> EventId {
+        // 0xff never occurs in UTF-8, so fields can't run into each other
+        let mut hash = fnv1a(FNV_OFFSET, self.machine.as_bytes());
+        hash = fnv1a(hash, &[0xff]);
+        hash = fnv1a(hash, self.session.as_bytes());
+        hash = fnv1a(hash, &[0xff]);
+        hash = fnv1a(hash, &self.timestamp_millis.to_le_bytes());
+        EventId(fnv1a(hash, self.command.as_bytes()))
+    }
+}
+
+impl From<EventRef<'_>> for Event {
+    fn from(event: EventRef<'_>) -> Self {
+        Self {
+            timestamp_millis: event.timestamp_millis,
+            command: event.command.to_string(),
+            endtime: event.endtime,
+            exit_code: event.exit_code,
+            folder: event.folder.into(),
+            machine: event.machine.into(),
+            session: event.session.into(),
+            root: event.root.cloned(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -96,9 +187,10 @@ mod tests {
             command: String::new(),
             endtime,
             exit_code: 0,
-            folder: String::new(),
-            machine: String::new(),
-            session: String::new(),
+            folder: "".into(),
+            machine: "".into(),
+            session: "".into(),
+            root: None,
         }
     }
 
@@ -130,18 +222,59 @@ mod tests {
             command: "sleep 5".to_string(),
             duration: 5.0,
             exit_code: 0,
-            folder: "/".to_string(),
-            machine: "m".to_string(),
-            session: "s".to_string(),
+            folder: "/".into(),
+            machine: "m".into(),
+            session: "s".into(),
         };
         let event = Event::from(json_event);
         assert_eq!(event.timestamp_millis, 1_000_000_000_000);
         assert_eq!(event.endtime, 1_000_000_000_000_i64 + 5 * 1000);
         assert_eq!(event.command, "sleep 5");
         assert_eq!(event.exit_code, 0);
-        assert_eq!(event.folder, "/");
-        assert_eq!(event.machine, "m");
-        assert_eq!(event.session, "s");
+        assert_eq!(&*event.folder, "/");
+        assert_eq!(&*event.machine, "m");
+        assert_eq!(&*event.session, "s");
+    }
+
+    #[test]
+    fn id_is_stable_and_ignores_outcome() {
+        let mut event = event_with_endtime(100);
+        event.command = "ls".to_string();
+        event.machine = "m".into();
+        // pinned, ids must not change between versions
+        assert_eq!(event.id(), EventId(0x652d_4a19_d896_fa8f));
+
+        let mut copy = event.clone();
+        copy.endtime = 200;
+        copy.folder = "/tmp".into();
+        copy.root = Some(Arc::from("team"));
+        assert_eq!(copy.id(), event.id());
+
+        // fields don't run into each other
+        let mut shifted = event.clone();
+        shifted.machine = "".into();
+        shifted.session = "m".into();
+        assert_ne!(shifted.id(), event.id());
+    }
+
+    #[test]
+    fn borrowed_roundtrip() {
+        let mut event = event_with_endtime(100);
+        event.command = "ls".to_string();
+        event.root = Some(Arc::from("team"));
+        let borrowed = event.borrowed();
+        assert_eq!(borrowed.id(), event.id());
+        assert!(Event::from(borrowed) == event);
+
+        let data = rmp_serde::to_vec(&event).unwrap();
+        let decoded: EventRef = rmp_serde::from_slice(&data).unwrap();
+        assert!(
+            decoded
+                == EventRef {
+                    root: None,
+                    ..borrowed
+                }
+        );
     }
 
     #[test]
@@ -1,20 +1,83 @@
-//! Supported history file formats: [`json_lines`] (deprecated) and [`rmp`] (current binary).
+//! Supported history file formats: [`json_lines`] (deprecated) and [`rmp`] (current binary),
+//! optionally compressed with zstd.
+use std::path::Path;
+
 pub mod json_lines;
 pub mod rmp;
 
 /// all formats we support
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Kind {
     JsonLines,
     Rmp,
+    /// [`Kind::Rmp`] compressed with zstd, for archives that are rarely read
+    RmpZst,
+    /// deletion records next to rmp files, see [`crate::tombstone`]
+    Tombstones,
 }
 
 impl Kind {
+    /// all kinds of history files with events, newest format first
+    pub const HISTORY: [Kind; 2] = [Kind::Rmp, Kind::RmpZst];
+
     /// get file extension for a format kind
     #[must_use]
     pub fn extension(&self) -> String {
         match self {
             Kind::JsonLines => "osh".to_string(),
             Kind::Rmp => "bosh".to_string(),
+            Kind::RmpZst => "bosh.zst".to_string(),
+            Kind::Tombstones => "tomb".to_string(),
         }
     }
+
+    /// the format of the file at `path` by its extension
+    #[must_use]
+    pub fn of(path: &Path) -> Option<Kind> {
+        let name = path.file_name()?.to_str()?;
+        // compound extensions first
+        [Kind::RmpZst, Kind::Rmp, Kind::JsonLines, Kind::Tombstones]
+            .into_iter()
+            .find(|kind| {
+                name.strip_suffix(&kind.extension())
+                    .is_some_and(|stem| stem.len() > 1 && stem.ends_with('.'))
+            })
+    }
+
+    /// `path` with its extension replaced by the one of `self`, e.g. `a.bosh.zst` for `a.bosh`
+    #[must_use]
+    pub fn with_extension(&self, path: &Path) -> std::path::PathBuf {
+        match Kind::of(path) {
+            Some(Kind::RmpZst) => path.with_extension("").with_extension(self.extension()),
+            _ => path.with_extension(self.extension()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_of_path() {
+        assert_eq!(Kind::of(Path::new("a/host.bosh")), Some(Kind::Rmp));
+        assert_eq!(Kind::of(Path::new("host.bosh.zst")), Some(Kind::RmpZst));
+        assert_eq!(Kind::of(Path::new("host.osh")), Some(Kind::JsonLines));
+        assert_eq!(Kind::of(Path::new("local.tomb")), Some(Kind::Tombstones));
+        assert_eq!(Kind::of(Path::new(".bosh")), None);
+        assert_eq!(Kind::of(Path::new("host.zst")), None);
+        assert_eq!(Kind::of(Path::new("hostbosh")), None);
+    }
+
+    #[test]
+    fn replace_extension() {
+        let path = Path::new("a/host.bosh");
+        let compressed = Kind::RmpZst.with_extension(path);
+        assert_eq!(compressed, Path::new("a/host.bosh.zst"));
+        assert_eq!(Kind::Rmp.with_extension(&compressed), path);
+        assert_eq!(
+            Kind::Rmp.with_extension(Path::new("host.osh")),
+            path.file_name().unwrap()
+        );
+    }
 }
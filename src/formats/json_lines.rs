@@ -92,14 +92,14 @@ mod test {
     use std::{fs::File, path::Path};
 
     use super::*;
-    use crate::mmap;
+    use crate::mmap::Mmap;
 
     #[test]
     fn test_parsing_osh_file() -> anyhow::Result<()> {
         let path = Path::new("tests/local.osh");
         let file = File::open(path).unwrap();
-        let data = mmap(&file);
-        let events = load_osh_events(data)?;
+        let data = Mmap::new(&file)?;
+        let events = load_osh_events(&data)?;
         assert_eq!(events.len(), 5);
         Ok(())
     }
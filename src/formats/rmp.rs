@@ -1,11 +1,25 @@
 //! Binary format using `rmp_serde`. Wire layout: each record is an 8-byte LE length prefix
 //! followed by a msgpack-encoded [`Event`]. This allows O(1) appends without deserialising the
 //! whole file.
-use std::io::Write;
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    os::{fd::AsRawFd, unix::fs::MetadataExt},
+    path::Path,
+};
 
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use rmp_serde::{decode, encode::to_vec};
+use serde::{Deserialize, Serialize};
 
-use crate::event::Event;
+use crate::{
+    event::{Event, EventRef},
+    formats::Kind,
+    mmap::Mmap,
+};
+
+/// zstd level of compressed files, they are written rarely so we trade speed for size
+const COMPRESSION_LEVEL: i32 = 19;
 
 #[derive(Debug)]
 pub struct BinaryWriter<W: Write> {
@@ -25,7 +39,16 @@ impl<W: Write> BinaryWriter<W> {
         reason = "contract shoul be that the event is consumed by write"
     )]
     pub fn write(&mut self, event: Event) -> anyhow::Result<()> {
-        let data = to_vec(&event)?;
+        self.write_record(&event)
+    }
+
+    /// write any record with the same framing as events, e.g. [`crate::tombstone::Tombstone`]s
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if serialisation or writing to file fails.
+    pub fn write_record<T: Serialize>(&mut self, record: &T) -> anyhow::Result<()> {
+        let data = to_vec(record)?;
         let mut buf = (data.len() as u64).to_le_bytes().to_vec();
         buf.extend(data);
         self.inner.write_all(&buf)?;
@@ -41,43 +64,304 @@ impl<W: Write> BinaryWriter<W> {
     }
 }
 
+/// read all [`Event`]s of the history file at `path`
+///
+/// # Errors
+///
+/// Will return an `Err` if the file can't be read or decoding fails.
+pub fn read_file(path: &Path) -> std::io::Result<Vec<Event>> {
+    read_events(&File::open(path)?, path)
+}
+
+/// read all [`Event`]s of `file` opened from `path`, e.g. one returned by [`lock`]
+///
+/// # Errors
+///
+/// Will return an `Err` if the file can't be read or decoding fails.
+pub fn read_events(file: &File, path: &Path) -> std::io::Result<Vec<Event>> {
+    let data = Mmap::new(file)?;
+    if data.is_empty() {
+        Ok(vec![])
+    } else if Kind::of(path) == Some(Kind::RmpZst) {
+        load_compressed_events(&data)
+    } else {
+        load_osh_events(&data)
+    }
+}
+
+/// open the history file at `path` with `options` and lock it exclusively, the lock is released
+/// when the file is dropped. Appends and rewrites (see [`rewrite_file`]) hold the lock, so an
+/// append can't land in between reading a file and renaming its rewrite over it. A file that was
+/// replaced or removed while waiting for the lock is opened again.
+///
+/// # Errors
+///
+/// Will return an `Err` if opening or locking the file fails.
+pub fn lock(path: &Path, options: &OpenOptions) -> std::io::Result<File> {
+    loop {
+        let file = options.open(path)?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let locked = file.metadata()?;
+        match std::fs::metadata(path) {
+            Ok(current) if (current.dev(), current.ino()) == (locked.dev(), locked.ino()) => {
+                return Ok(file);
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// [`lock`] the history file at `path` for a rewrite, creating it if it is missing. Returns
+/// whether it was created.
+fn lock_or_create(path: &Path) -> std::io::Result<(File, bool)> {
+    loop {
+        match lock(path, OpenOptions::new().read(true)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            locked => return locked.map(|file| (file, false)),
+        }
+        match lock(
+            path,
+            OpenOptions::new().read(true).write(true).create_new(true),
+        ) {
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            locked => return locked.map(|file| (file, true)),
+        }
+    }
+}
+
+/// write `events` to `inner` and return it flushed
+fn write_events<W: Write>(inner: W, events: impl IntoIterator<Item = Event>) -> anyhow::Result<W> {
+    let mut writer = BinaryWriter::new(inner);
+    for event in events {
+        writer.write(event)?;
+    }
+    writer.flush()?;
+    Ok(writer.inner)
+}
+
+/// write `events` to `inner` as the history file at `path`, compressed for `.bosh.zst`
+fn encode<W: Write>(
+    path: &Path,
+    inner: W,
+    events: impl IntoIterator<Item = Event>,
+) -> anyhow::Result<W> {
+    if Kind::of(path) == Some(Kind::RmpZst) {
+        let encoder = zstd::Encoder::new(inner, COMPRESSION_LEVEL)?;
+        Ok(write_events(encoder, events)?.finish()?)
+    } else {
+        write_events(inner, events)
+    }
+}
+
+/// size of the history file at `path` with `events` as [`rewrite_file`] writes it
+///
+/// # Errors
+///
+/// Will return an `Err` if serialisation or compression fails.
+pub fn encoded_len(path: &Path, events: impl IntoIterator<Item = Event>) -> anyhow::Result<u64> {
+    Ok(encode(path, Vec::new(), events)?.len() as u64)
+}
+
+/// replace the history file at `path` with the events `edit` returns for its current events (none
+/// if it is missing), `None` leaves the file as it is. The events are written to a temporary file
+/// next to it that is renamed over `path`, so readers never see a partial file and existing
+/// memory maps stay valid (see [`crate::mmap::Mmap`]). The file is [`lock`]ed from reading until
+/// the rename, appends wait and go to the rewritten file. Files ending in `.bosh.zst` are
+/// compressed.
+///
+/// # Errors
+///
+/// Will return an `Err` if reading the file, `edit`, writing the temporary file or renaming it
+/// fails.
+pub fn rewrite_file<E: IntoIterator<Item = Event>>(
+    path: &Path,
+    edit: impl FnOnce(Vec<Event>) -> anyhow::Result<Option<E>>,
+) -> anyhow::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or(anyhow::anyhow!("{} is not a file", path.display()))?;
+    let (locked, created) = lock_or_create(path)?;
+    let edited = read_events(&locked, path)
+        .map_err(anyhow::Error::from)
+        .and_then(edit);
+    let events = match edited {
+        Ok(Some(events)) => events,
+        edited => {
+            // don't leave a file behind that only exists for the lock
+            if created && locked.metadata().is_ok_and(|metadata| metadata.len() == 0) {
+                let _ = std::fs::remove_file(path);
+            }
+            return edited.map(|_| ());
+        }
+    };
+    let temp = path.with_file_name(format!(".{}.tmp", name.to_string_lossy()));
+    let result = (|| {
+        let file = File::create(&temp)?;
+        encode(path, BufWriter::new(&file), events)?.flush()?;
+        file.sync_all()?;
+        std::fs::rename(&temp, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+/// parse and collect all [`Event`]s in the zstd compressed slice, see [`Kind::RmpZst`]
+///
+/// # Errors
+///
+/// Will return an `Err` if decompression or decoding fails.
+pub fn load_compressed_events(data: &[u8]) -> std::io::Result<Vec<Event>> {
+    load_osh_events(&zstd::decode_all(data)?)
+}
+
 /// parse and collect all [`Event`]s in the slice
 ///
 /// # Errors
 ///
 /// Will return an `Err` decoding fails (truncation or invalid format).
 pub fn load_osh_events(data: &[u8]) -> std::io::Result<Vec<Event>> {
+    load_records(data)
+}
+
+/// parse and collect all [`EventRef`]s in the slice without copying their strings
+///
+/// # Errors
+///
+/// Will return an `Err` decoding fails (truncation or invalid format).
+pub fn load_event_refs(data: &[u8]) -> std::io::Result<Vec<EventRef<'_>>> {
+    load_records(data)
+}
+
+/// slices at least this large are decoded in parallel by [`load_event_refs_parallel`], below the
+/// scan and thread handoff cost more than they save
+pub const PARALLEL_DECODE_BYTES: usize = 1 << 20;
+
+/// [`load_event_refs`] for large slices, e.g. a long-lived `local.bosh`: the slice is split into
+/// runs of whole records (see [`split_records`]) that are decoded in parallel. Falls back to
+/// [`load_event_refs`] with a single thread.
+///
+/// # Errors
+///
+/// Will return an `Err` decoding fails (truncation or invalid format).
+pub fn load_event_refs_parallel(data: &[u8]) -> std::io::Result<Vec<EventRef<'_>>> {
+    let threads = rayon::current_num_threads();
+    if data.len() < PARALLEL_DECODE_BYTES || threads == 1 {
+        return load_event_refs(data);
+    }
+    let chunks = split_records(data, threads * 4)?;
+    let decoded: Vec<Vec<EventRef>> = chunks
+        .into_par_iter()
+        .map(load_event_refs)
+        .collect::<std::io::Result<_>>()?;
+    Ok(decoded.concat())
+}
+
+/// iterate over the records of `data`, each with its 8-byte length prefix, see
+/// [`BinaryWriter::write_record`]. Only the length prefixes are read. A length prefix that is
+/// incomplete or points past the end of `data` yields an `UnexpectedEof` error and ends the
+/// iteration.
+pub fn records(data: &[u8]) -> impl Iterator<Item = std::io::Result<&[u8]>> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let split = rest
+            .get(..8)
+            .and_then(|prefix| {
+                let size_bytes: [u8; 8] = prefix.try_into().ok()?;
+                #[expect(
+                    clippy::cast_possible_truncation,
+                    reason = "assuming above write was used"
+                )]
+                let event_size = u64::from_le_bytes(size_bytes) as usize;
+                event_size.checked_add(8)
+            })
+            .and_then(|end| rest.split_at_checked(end));
+        let Some((record, tail)) = split else {
+            rest = &[];
+            return Some(Err(std::io::ErrorKind::UnexpectedEof.into()));
+        };
+        rest = tail;
+        Some(Ok(record))
+    })
+}
+
+/// split `data` into about `chunks` slices of whole records of similar size. Only the length
+/// prefixes are read, which is much cheaper than decoding the records.
+///
+/// # Errors
+///
+/// Will return an `Err` if a length prefix points past the end of `data`.
+pub fn split_records(data: &[u8], chunks: usize) -> std::io::Result<Vec<&[u8]>> {
+    let target = data.len().div_ceil(chunks.max(1)).max(1);
+    let mut slices = Vec::with_capacity(chunks);
+    let (mut start, mut cursor) = (0, 0);
+    for record in records(data) {
+        cursor += record?.len();
+        if cursor - start >= target {
+            slices.extend(data.get(start..cursor));
+            start = cursor;
+        }
+    }
+    slices.extend(data.get(start..cursor).filter(|rest| !rest.is_empty()));
+    Ok(slices)
+}
+
+/// the first and last record of `data`, with their length prefix. Only the length prefixes are
+/// read, like in [`split_records`].
+///
+/// # Errors
+///
+/// Will return an `Err` if a length prefix points past the end of `data`.
+pub fn outer_records(data: &[u8]) -> std::io::Result<Option<(&[u8], &[u8])>> {
+    let mut outer: Option<(&[u8], &[u8])> = None;
+    for record in records(data) {
+        let record = record?;
+        outer = Some((outer.map_or(record, |(first, _)| first), record));
+    }
+    Ok(outer)
+}
+
+/// decode a single record as returned by [`records`]
+fn decode_record<'a, T: Deserialize<'a>>(record: &'a [u8]) -> std::io::Result<T> {
+    decode::from_slice(record.get(8..).unwrap_or_default())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// parse and collect all records in the slice, see [`BinaryWriter::write_record`]. Records may
+/// borrow from `data`.
+///
+/// # Errors
+///
+/// Will return an `Err` decoding fails (truncation or invalid format).
+pub fn load_records<'a, T: Deserialize<'a>>(data: &'a [u8]) -> std::io::Result<Vec<T>> {
+    records(data).map(|record| decode_record(record?)).collect()
+}
+
+/// parse all complete [`Event`]s in the slice and ignore a truncated record at the end, e.g. one
+/// that is still being appended. Returns the events and the number of bytes consumed.
+///
+/// # Errors
+///
+/// Will return an `Err` if decoding a complete record fails.
+pub fn load_complete_events(data: &[u8]) -> std::io::Result<(Vec<Event>, usize)> {
     let mut events = Vec::new();
-    let mut cursor = 0;
-
-    while cursor < data.len() {
-        #[expect(clippy::missing_panics_doc, reason = "infallible")]
-        #[expect(
-            clippy::expect_used,
-            reason = "errors if we can't read exactly 8 bytes"
-        )]
-        let size_bytes: [u8; 8] = data
-            .get(cursor..cursor + 8)
-            .ok_or(std::io::ErrorKind::UnexpectedEof)?
-            .try_into()
-            .expect("slice is exactly 8 bytes");
-        #[expect(
-            clippy::cast_possible_truncation,
-            reason = "assuming above write was used"
-        )]
-        let event_size = u64::from_le_bytes(size_bytes) as usize;
-        cursor += 8;
-
-        let event: Event = decode::from_slice(
-            data.get(cursor..cursor + event_size)
-                .ok_or(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?,
-        )
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        events.push(event);
-        cursor += event_size;
-    }
-
-    Ok(events)
+    let mut consumed = 0;
+    // a truncated record, or a length that overflows, can only be a tail that is still being
+    // written
+    for record in records(data).map_while(Result::ok) {
+        events.push(decode_record(record)?);
+        consumed += record.len();
+    }
+    Ok((events, consumed))
 }
 
 #[cfg(test)]
@@ -119,8 +403,218 @@ mod tests {
 
         let read_events = load_osh_events(buffer.as_ref())?;
         assert_eq!(read_events.len(), num_events);
-        assert!(read_events.into_iter().eq(events.into_iter()));
+        assert!(read_events.iter().eq(events.iter()));
+        let borrowed = load_event_refs(buffer.as_ref())?;
+        assert!(borrowed.into_iter().eq(events.iter().map(Event::borrowed)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn split_records_keeps_records_whole() -> anyhow::Result<()> {
+        let data: Vec<u8> = (1u8..=255).cycle().take(5000).collect();
+        let mut u = Unstructured::new(&data);
+        let mut buffer = Vec::new();
+        let mut writer = BinaryWriter::new(&mut buffer);
+        let mut events = Vec::new();
+        for _ in 0..50 {
+            let event = crate::event::Event::arbitrary(&mut u)?;
+            event.clone().write(&mut writer)?;
+            events.push(event);
+        }
+
+        for chunks in [1, 3, 7, 50, 200] {
+            let slices = split_records(&buffer, chunks)?;
+            assert!(slices.len() <= chunks.min(50));
+            assert_eq!(
+                slices.iter().map(|slice| slice.len()).sum::<usize>(),
+                buffer.len()
+            );
+            let decoded: Vec<Event> = slices
+                .iter()
+                .map(|slice| load_osh_events(slice))
+                .collect::<std::io::Result<Vec<_>>>()?
+                .concat();
+            assert!(decoded == events);
+        }
+        assert!(split_records(&buffer, 4)?.len() > 1);
+        assert!(split_records(&buffer[..buffer.len() - 1], 4).is_err());
+        assert!(split_records(&[], 4)?.is_empty());
+
+        let (first, last) = outer_records(&buffer)?.unwrap();
+        assert!(load_osh_events(first)? == events[..1]);
+        assert!(load_osh_events(last)? == events[49..]);
+        assert!(outer_records(&buffer[..buffer.len() - 1]).is_err());
+        assert!(outer_records(&[])?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_decode_matches_sequential() -> anyhow::Result<()> {
+        let mut buffer = Vec::new();
+        let mut i = 0;
+        while buffer.len() < PARALLEL_DECODE_BYTES * 2 {
+            let mut writer = BinaryWriter::new(&mut buffer);
+            Event {
+                timestamp_millis: i,
+                command: format!("cargo test {i}"),
+                endtime: i,
+                exit_code: 0,
+                folder: "/home/someone/projects/osh-oxy".into(),
+                machine: "laptop".into(),
+                session: "0b9f1a5e-4c2d-4f7e-9a51-3c8d2e6f7a10".into(),
+                root: None,
+            }
+            .write(&mut writer)?;
+            i += 1;
+        }
+
+        // regardless of the cores of the machine running the test
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build()?;
+        let parallel = pool.install(|| load_event_refs_parallel(&buffer))?;
+        assert!(parallel == load_event_refs(&buffer)?);
+        assert!(parallel.is_sorted_by_key(|event| event.endtime));
+        let truncated = &buffer[..buffer.len() - 1];
+        assert!(
+            pool.install(|| load_event_refs_parallel(truncated))
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn complete_events_skip_truncated_tail() -> anyhow::Result<()> {
+        let data: Vec<u8> = (1u8..=255).cycle().take(1000).collect();
+        let mut u = Unstructured::new(&data);
+
+        let mut buffer = Vec::new();
+        let mut writer = BinaryWriter::new(&mut buffer);
+        crate::event::Event::arbitrary(&mut u)?.write(&mut writer)?;
+        let complete = buffer.len();
+        crate::event::Event::arbitrary(&mut u)?.write(&mut BinaryWriter::new(&mut buffer))?;
+
+        for end in [complete, complete + 4, buffer.len() - 1] {
+            let (events, consumed) = load_complete_events(&buffer[..end])?;
+            assert_eq!(events.len(), 1);
+            assert_eq!(consumed, complete);
+        }
+        let (events, consumed) = load_complete_events(&buffer)?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(consumed, buffer.len());
+
+        // a garbage length that overflows is a truncated tail as well
+        buffer.truncate(complete);
+        buffer.extend(u64::MAX.to_le_bytes());
+        let (events, consumed) = load_complete_events(&buffer)?;
+        assert_eq!((events.len(), consumed), (1, complete));
+        assert!(load_osh_events(&buffer).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rewrite_file_replaces_contents() -> anyhow::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("local.bosh");
+        let data: Vec<u8> = (1u8..=255).cycle().take(1000).collect();
+        let mut u = Unstructured::new(&data);
+        let old = crate::event::Event::arbitrary(&mut u)?;
+        rewrite_file(&path, |events| {
+            assert!(events.is_empty());
+            Ok(Some([old.clone()]))
+        })?;
+        let old_contents = std::fs::read(&path)?;
+        let mapped = crate::mmap::Mmap::new(&File::open(&path)?)?;
+
+        let events = vec![crate::event::Event::arbitrary(&mut u)?; 2];
+        rewrite_file(&path, |current| {
+            assert!(current == [old]);
+            Ok(Some(events.clone()))
+        })?;
+
+        assert!(load_osh_events(&std::fs::read(&path)?)? == events);
+        assert_eq!(&*mapped, &old_contents[..]);
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn rewrite_file_can_leave_files_alone() -> anyhow::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("local.bosh");
+        rewrite_file(&path, |_| Ok(None::<Vec<Event>>))?;
+        assert!(!path.exists());
+        assert!(
+            rewrite_file(&path, |_| -> anyhow::Result<Option<Vec<Event>>> {
+                anyhow::bail!("failed")
+            })
+            .is_err()
+        );
+        assert!(!path.exists());
+
+        std::fs::write(&path, b"")?;
+        rewrite_file(&path, |_| Ok(None::<Vec<Event>>))?;
+        assert!(path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn appends_wait_for_rewrites() -> anyhow::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("local.bosh");
+        let data: Vec<u8> = (1u8..=255).cycle().take(1000).collect();
+        let mut u = Unstructured::new(&data);
+        let (kept, appended) = (Event::arbitrary(&mut u)?, Event::arbitrary(&mut u)?);
+
+        let (locked, wait) = std::sync::mpsc::channel();
+        let result = std::thread::scope(|scope| -> anyhow::Result<()> {
+            let path = &path;
+            let appended = &appended;
+            let thread = scope.spawn(move || -> anyhow::Result<()> {
+                wait.recv()?;
+                let file = lock(path, OpenOptions::new().create(true).append(true))?;
+                appended.clone().write(&mut BinaryWriter::new(file))
+            });
+            rewrite_file(path, |_| {
+                locked.send(())?;
+                // the appender is blocked on the lock of the file replaced below
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                Ok(Some([kept.clone()]))
+            })?;
+            thread
+                .join()
+                .map_err(|_| anyhow::anyhow!("appender panicked"))?
+        });
+        result?;
+
+        assert!(read_file(&path)? == [kept, appended]);
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_files_roundtrip() -> anyhow::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let plain = dir.path().join("host.bosh");
+        let compressed = dir.path().join("host.bosh.zst");
+        let events: Vec<Event> = (0..100)
+            .map(|i| Event {
+                timestamp_millis: i,
+                command: format!("cargo test {i}"),
+                endtime: i,
+                exit_code: 0,
+                folder: "/home/someone/projects/osh-oxy".into(),
+                machine: "laptop".into(),
+                session: "0b9f1a5e-4c2d-4f7e-9a51-3c8d2e6f7a10".into(),
+                root: None,
+            })
+            .collect();
+        rewrite_file(&plain, |_| Ok(Some(events.clone())))?;
+        rewrite_file(&compressed, |_| Ok(Some(events.clone())))?;
 
+        assert!(read_file(&compressed)? == events);
+        assert!(read_file(&plain)? == events);
+        let size = |path: &Path| std::fs::metadata(path).map(|metadata| metadata.len());
+        assert!(size(&compressed)? * 5 < size(&plain)?);
         Ok(())
     }
 }
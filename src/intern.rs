@@ -0,0 +1,136 @@
+//! String interning for the fields that repeat across a history: every event of a session shares
+//! its machine and session, and most commands run in a handful of folders. Loaded events share one
+//! allocation per distinct string instead of owning a copy, and the TUI compares [`Symbol`]s
+//! instead of strings when filtering.
+use std::{collections::HashMap, sync::Arc};
+
+use crate::event::{Event, EventRef};
+
+/// Handle of an interned string, only meaningful for the [`Interner`] that created it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// Deduplicates strings into shared handles.
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: HashMap<Arc<str>, Symbol>,
+    strings: Vec<Arc<str>>,
+}
+
+impl Interner {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the symbol of `string`, interning it if it is new
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than `u32::MAX` strings are interned.
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(string) {
+            return symbol;
+        }
+        #[expect(
+            clippy::expect_used,
+            reason = "a history doesn't have 4 billion folders"
+        )]
+        let symbol = Symbol(u32::try_from(self.strings.len()).expect("too many strings"));
+        let shared: Arc<str> = Arc::from(string);
+        self.strings.push(Arc::clone(&shared));
+        self.symbols.insert(shared, symbol);
+        symbol
+    }
+
+    /// the symbol of `string` if it was interned
+    #[must_use]
+    pub fn get(&self, string: &str) -> Option<Symbol> {
+        self.symbols.get(string).copied()
+    }
+
+    /// the shared handle of `string`, interning it if it is new
+    pub fn shared(&mut self, string: &str) -> Arc<str> {
+        let symbol = self.intern(string);
+        Arc::clone(self.resolve(symbol))
+    }
+
+    /// the string of `symbol`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `symbol` was created by another interner.
+    #[must_use]
+    pub fn resolve(&self, symbol: Symbol) -> &Arc<str> {
+        #[expect(
+            clippy::indexing_slicing,
+            reason = "symbols are positions in strings, which only grows"
+        )]
+        &self.strings[symbol.0 as usize]
+    }
+
+    /// number of distinct strings
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// an owned copy of `event` whose folder, machine and session are shared
+    pub fn event(&mut self, event: EventRef<'_>) -> Event {
+        Event {
+            timestamp_millis: event.timestamp_millis,
+            command: event.command.to_string(),
+            endtime: event.endtime,
+            exit_code: event.exit_code,
+            folder: self.shared(event.folder),
+            machine: self.shared(event.machine),
+            session: self.shared(event.session),
+            root: event.root.cloned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_strings_share_a_symbol() {
+        let mut interner = Interner::new();
+        let home = interner.intern("/home");
+        let tmp = interner.intern("/tmp");
+        assert_ne!(home, tmp);
+        assert_eq!(interner.intern(&String::from("/home")), home);
+        assert_eq!(interner.get("/tmp"), Some(tmp));
+        assert_eq!(interner.get("/var"), None);
+        assert_eq!(interner.resolve(home).as_ref(), "/home");
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn events_share_fields() {
+        let mut interner = Interner::new();
+        let event = Event {
+            timestamp_millis: 1,
+            command: "ls".to_string(),
+            endtime: 1,
+            exit_code: 0,
+            folder: "/".into(),
+            machine: "m".into(),
+            session: "s".into(),
+            root: None,
+        };
+        let a = interner.event(event.borrowed());
+        let b = interner.event(event.borrowed());
+        assert!(a == event);
+        assert!(Arc::ptr_eq(&a.folder, &b.folder));
+        assert!(Arc::ptr_eq(&a.machine, &b.machine));
+        assert!(Arc::ptr_eq(&a.session, &b.session));
+        assert!(!Arc::ptr_eq(&a.folder, &event.folder));
+    }
+}
@@ -1,59 +1,50 @@
 use std::{
-    collections::HashSet,
+    collections::{BinaryHeap, HashMap, HashSet, hash_map::Entry},
+    fmt::Display,
     fs::File,
-    os::fd::AsRawFd,
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, OnceLock},
 };
 
 use anyhow::anyhow;
 use glob::glob;
-use rayon::{
-    iter::{IntoParallelRefIterator, ParallelIterator},
-    slice::ParallelSliceMut,
-};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
-    event::Event,
+    arena::Arena,
+    cache::Cache,
+    event::{Event, EventRef},
     formats::{Kind, rmp},
+    intern::Interner,
+    mmap::Mmap,
+    oshignore::IgnoreRules,
+    timings::Timings,
+    tombstone::Tombstones,
 };
 
+pub mod arena;
+pub mod cache;
 pub mod commands;
+pub mod config;
 pub mod event;
 pub mod formats;
+pub mod intern;
 pub mod matcher;
+pub mod mmap;
+pub mod oshignore;
+pub mod output;
+pub mod prune;
+pub mod redact;
+#[cfg(test)]
+mod testing;
+pub mod timings;
+pub mod tombstone;
 pub mod ui;
-
-/// memory map `file`
-/// # Panics
-///
-/// Will panic if statx or memory mapping fails, and if we can't set the `MADV_SEQUENTIAL` hint.
-#[must_use]
-pub fn mmap(file: &File) -> &'_ [u8] {
-    #[expect(clippy::unwrap_used, reason = "panic if statx fails")]
-    let len = file.metadata().unwrap().len();
-    #[expect(clippy::expect_used, reason = "")]
-    let len = libc::size_t::try_from(len).expect("casting file size to size_t is not truncating");
-    unsafe {
-        let ptr = libc::mmap(
-            std::ptr::null_mut(),
-            len,
-            libc::PROT_READ,
-            libc::MAP_SHARED,
-            file.as_raw_fd(),
-            0,
-        );
-        if ptr == libc::MAP_FAILED {
-            panic!("{:?}", std::io::Error::last_os_error());
-        } else {
-            let r = libc::madvise(ptr, len, libc::MADV_SEQUENTIAL);
-            assert!(r == 0, "{:?}", std::io::Error::last_os_error());
-            std::slice::from_raw_parts(ptr as *const u8, len)
-        }
-    }
-}
+pub mod watch;
 
 /// discover all osh files of `kind` under `root`, recursively.
-fn discover_files(root: &Path, kind: &formats::Kind) -> anyhow::Result<HashSet<PathBuf>> {
+fn discover_files(root: &Path, kind: &formats::Kind) -> anyhow::Result<Vec<PathBuf>> {
     let pattern = format!(
         "{}/**/*.{}",
         root.to_str()
@@ -62,62 +53,631 @@ fn discover_files(root: &Path, kind: &formats::Kind) -> anyhow::Result<HashSet<P
     );
     let files = match glob(&pattern) {
         Err(_) => unreachable!("pattern is valid"),
-        Ok(matches) => matches
-            .filter_map(Result::ok)
-            .filter_map(|path| path.canonicalize().ok())
-            .collect(),
+        Ok(matches) => matches.filter_map(Result::ok).collect(),
+    };
+    Ok(files)
+}
+
+/// the file a sync tool created `path` as a conflict copy of, e.g. `host.bosh` for Syncthing's
+/// `host.sync-conflict-20261001-120000-ABCDEFG.bosh` or Nextcloud's and Dropbox's
+/// `host (conflicted copy 2026-10-01).bosh`. `None` if `path` is not a conflict copy of a file of
+/// a known [`Kind`].
+#[must_use]
+pub fn conflict_original(path: &Path) -> Option<PathBuf> {
+    // the whole extension, sync tools mark the copy in front of `.bosh.zst`
+    let extension = Kind::of(path)?.extension();
+    let stem = path
+        .file_name()?
+        .to_str()?
+        .strip_suffix(&extension)?
+        .strip_suffix('.')?;
+    let original = if let Some((original, _)) = stem.split_once(".sync-conflict-") {
+        original
+    } else {
+        let (original, copy) = stem.strip_suffix(')')?.rsplit_once(" (")?;
+        if !copy.contains("conflicted copy") {
+            return None;
+        }
+        original
+    };
+    Some(path.with_file_name(format!("{original}.{extension}")))
+}
+
+/// environment variable to override the default history root `~/.osh`, a `:` separated list of
+/// roots (see [`Root`])
+pub const OSH_HOME: &str = "OSH_HOME";
+
+/// A folder with history files and the label its events are shown with, written as `label=path`
+/// or just `path` (labeled by its folder name).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Root {
+    pub label: String,
+    pub path: PathBuf,
+}
+
+impl Root {
+    fn from_path(path: PathBuf) -> Self {
+        let label = path
+            .file_name()
+            .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+            .into_owned();
+        Self { label, path }
+    }
+}
+
+impl FromStr for Root {
+    type Err = std::convert::Infallible;
+    fn from_str(root: &str) -> Result<Self, Self::Err> {
+        Ok(match root.split_once('=') {
+            Some((label, path)) if !label.is_empty() => Self {
+                label: label.to_string(),
+                path: PathBuf::from(path),
+            },
+            _ => Self::from_path(PathBuf::from(root)),
+        })
+    }
+}
+
+/// Where history is read from and new events are written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OshHome {
+    /// all history files below the roots are loaded, the first root takes precedence
+    pub roots: Vec<Root>,
+    /// history file new events are appended to
+    pub file: PathBuf,
+    /// archive folder that is skipped (see [`config::Retention`]), `None` loads archived history
+    /// like any other
+    pub archive: Option<PathBuf>,
+}
+
+impl OshHome {
+    /// `roots` default to `$OSH_HOME` or `~/.osh`, `file` to `local.bosh` in the first root.
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if no root is given and `home_dir()` discovery fails.
+    pub fn new(roots: Vec<Root>, file: Option<PathBuf>) -> anyhow::Result<Self> {
+        let mut roots = roots;
+        if roots.is_empty()
+            && let Some(osh_home) = std::env::var_os(OSH_HOME)
+        {
+            roots = std::env::split_paths(&osh_home)
+                .filter(|path| !path.as_os_str().is_empty())
+                .map(|path| match path.to_str() {
+                    Some(root) => Root::from_str(root).unwrap_or_else(|e| match e {}),
+                    None => Root::from_path(path),
+                })
+                .collect();
+        }
+        if roots.is_empty() {
+            let home_dir = home::home_dir().ok_or(anyhow!("no home directory"))?;
+            roots.push(Root::from_path(home_dir.join(".osh")));
+        }
+
+        let file = match (file, roots.first()) {
+            (Some(file), _) => file,
+            (None, Some(root)) => root.path.join(format!("local.{}", Kind::Rmp.extension())),
+            (None, None) => unreachable!("there is always at least one root"),
+        };
+        Ok(Self {
+            roots,
+            file,
+            archive: None,
+        })
+    }
+
+    /// label of the first root, used for `file` if it lives outside of all roots
+    #[must_use]
+    pub fn default_label(&self) -> &str {
+        self.roots.first().map_or("", |root| root.label.as_str())
+    }
+
+    /// labels of all roots in order
+    #[must_use]
+    pub fn labels(&self) -> Vec<String> {
+        self.roots.iter().map(|root| root.label.clone()).collect()
+    }
+
+    /// `config.toml` in the first root (see [`config::Config`])
+    #[must_use]
+    pub fn config_path(&self) -> PathBuf {
+        let root = self.roots.first().map_or(Path::new(""), |root| &root.path);
+        root.join(config::FILE_NAME)
+    }
+
+    /// folder of the [`cache`] in the first root, history without the archive has its own so
+    /// searches with and without it don't rebuild each other's
+    #[must_use]
+    pub fn cache_dir(&self) -> PathBuf {
+        let root = self.roots.first().map_or(Path::new(""), |root| &root.path);
+        let dir = root.join(cache::DIR_NAME);
+        match self.archive {
+            Some(_) => dir.join("without-archive"),
+            None => dir,
+        }
+    }
+}
+
+/// Why a history file found below a root is not loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// excluded by a pattern in the `.oshignore` of the root
+    Ignored { pattern: String },
+    /// the same file is already loaded through another root, or another path in the same root
+    Duplicate { of: PathBuf },
+    /// the file is in the skipped archive folder of the [`OshHome`]
+    Archived,
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Ignored { pattern } => {
+                write!(f, "ignored by {pattern:?} in {}", oshignore::FILE_NAME)
+            }
+            SkipReason::Duplicate { of } => write!(f, "duplicate of {}", of.display()),
+            SkipReason::Archived => write!(f, "archived"),
+        }
+    }
+}
+
+/// History files found below the roots of an [`OshHome`].
+#[derive(Debug, Default)]
+pub struct Discovery {
+    /// canonicalized paths of the files to load and the labels of their roots
+    pub files: HashMap<PathBuf, Arc<str>>,
+    /// files that were found but are not loaded, as found below the root
+    pub skipped: Vec<(PathBuf, SkipReason)>,
+    /// loaded conflict copies of sync tools and their originals (see [`conflict_original`]), their
+    /// events are merged with the original ones by [`Event::id`]
+    pub conflicts: HashMap<PathBuf, PathBuf>,
+}
+
+/// discover all parsable osh files under the roots of `home` for a specific format, including
+/// `home.file` if it lives somewhere else. Files excluded by the `.oshignore` of their root are
+/// skipped, files reachable from several roots (e.g. through symlinks) are only loaded once,
+/// labeled by the first root. Files in `home.archive` are skipped as well. `home.file` is always
+/// loaded.
+///
+/// # Errors
+///
+/// Will return an `Err` if a root path or its `.oshignore` is invalid.
+pub fn discover(home: &OshHome, kind: &formats::Kind) -> anyhow::Result<Discovery> {
+    let mut discovery = Discovery::default();
+    let files = &mut discovery.files;
+    let archive = home
+        .archive
+        .as_ref()
+        .and_then(|archive| archive.canonicalize().ok());
+    for root in &home.roots {
+        let label: Arc<str> = Arc::from(root.label.as_str());
+        let rules = IgnoreRules::load(&root.path)?;
+        for found in discover_files(&root.path, kind)? {
+            let Ok(file) = found.canonicalize() else {
+                continue;
+            };
+            let reason = if let Some(pattern) = rules.ignored_by(&found) {
+                SkipReason::Ignored { pattern }
+            } else if archive
+                .as_ref()
+                .is_some_and(|archive| file.starts_with(archive))
+            {
+                SkipReason::Archived
+            } else {
+                match files.entry(file) {
+                    Entry::Occupied(entry) => SkipReason::Duplicate {
+                        of: entry.key().clone(),
+                    },
+                    Entry::Vacant(entry) => {
+                        if let Some(original) = conflict_original(entry.key()) {
+                            discovery.conflicts.insert(entry.key().clone(), original);
+                        }
+                        entry.insert(Arc::clone(&label));
+                        continue;
+                    }
+                }
+            };
+            discovery.skipped.push((found, reason));
+        }
+    }
+    let own = match kind {
+        Kind::Tombstones => tombstone::sidecar(&home.file),
+        Kind::JsonLines | Kind::Rmp | Kind::RmpZst => home.file.clone(),
     };
+    if Kind::of(&own).as_ref() == Some(kind)
+        && let Ok(file) = own.canonicalize()
+    {
+        files
+            .entry(file)
+            .or_insert_with(|| Arc::from(home.default_label()));
+    }
+    Ok(discovery)
+}
+
+/// the files [`discover`] loads
+///
+/// # Errors
+///
+/// Will return an `Err` if a root path or its `.oshignore` is invalid.
+pub fn osh_files(
+    home: &OshHome,
+    kind: &formats::Kind,
+) -> anyhow::Result<HashMap<PathBuf, Arc<str>>> {
+    Ok(discover(home, kind)?.files)
+}
+
+/// the history files of `kind` that belong to this machine: the ones in the first root and
+/// `home.file`. Later roots are usually shared (e.g. the history of a team) and only read, so
+/// commands that rewrite or remove files leave them alone.
+///
+/// # Errors
+///
+/// Will return an `Err` if discovery fails.
+pub fn own_files(home: &OshHome, kind: &formats::Kind) -> anyhow::Result<Vec<PathBuf>> {
+    let first = home
+        .roots
+        .first()
+        .and_then(|root| root.path.canonicalize().ok());
+    let file = home.file.canonicalize().ok();
+    let mut files: Vec<PathBuf> = osh_files(home, kind)?
+        .into_keys()
+        .filter(|path| {
+            first.as_ref().is_some_and(|root| path.starts_with(root)) || Some(path) == file.as_ref()
+        })
+        .collect();
+    files.sort();
     Ok(files)
 }
 
-/// discover all parsable osh files under `~/.osh` for a specific format
+/// A history file that could not be loaded, the remaining files are loaded regardless.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadError {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+impl LoadError {
+    pub fn new(path: &Path, reason: impl Display) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.reason)
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// load all binary osh files of `home` and return a merged and sorted vector of all events
+///
+/// # Errors
+///
+/// Will return an `Err` if collecting, memory mapping and parsing of any osh file fails.
+pub fn load_sorted(home: &OshHome) -> anyhow::Result<Vec<Event>> {
+    load_sorted_timed(home, &Timings::default())
+}
+
+/// [`load_sorted`] recording the phases of loading in `timings`
+///
+/// # Errors
+///
+/// Will return an `Err` if collecting, memory mapping and parsing of any osh file fails.
+pub fn load_sorted_timed(home: &OshHome, timings: &Timings) -> anyhow::Result<Vec<Event>> {
+    let arena = Arena::new();
+    let mut interner = Interner::new();
+    let mut events = Vec::new();
+    let mut errors = Vec::new();
+    load_streaming_timed(
+        home,
+        &arena,
+        |event| {
+            events.push(interner.event(event));
+            true
+        },
+        |error| errors.push(error),
+        timings,
+    )?;
+    if let Some(error) = errors.into_iter().next() {
+        return Err(error.into());
+    }
+    // in case a file broke the ordering of the merge (see `merge_files`), cheap if sorted
+    events.sort_by(|a, b| b.cmp(a));
+    Ok(events)
+}
+
+/// load all binary osh files of `home`, compressed ones included, and pass their events to `emit`,
+/// newest first, until it returns `false`. The events borrow from the files, which are kept in
+/// `arena`. Events removed by a [`tombstone`] and copies of an already emitted event (see
+/// [`Event::id`]) are skipped. Files that fail to load are passed to `report` and skipped. Returns
+/// the number of bytes loaded per file, so a [`watch::HistoryWatcher`] can continue from there.
+///
+/// The merged events are kept in a [`cache`], so only bytes appended since the last load are
+/// decoded. Without a valid cache the files are merged and the cache is rebuilt once all events
+/// were emitted.
 ///
 /// # Errors
 ///
-/// Will return an `Err` if `home_dir()` discovery fails.
-pub fn osh_files(kind: &formats::Kind) -> anyhow::Result<HashSet<PathBuf>> {
-    let home_dir = home::home_dir().ok_or(anyhow!("no home directory"))?;
-    discover_files(&home_dir.join(".osh"), kind)
+/// Will return an `Err` if collecting osh files fails.
+pub fn load_streaming<'a>(
+    home: &OshHome,
+    arena: &'a Arena,
+    emit: impl FnMut(EventRef<'a>) -> bool,
+    report: impl FnMut(LoadError),
+) -> anyhow::Result<HashMap<PathBuf, u64>> {
+    load_streaming_timed(home, arena, emit, report, &Timings::default())
 }
 
-/// load all binary osh files in `~/.osh` and return a merged and sorted vector of all events
+/// [`load_streaming`] recording the phases of loading in `timings`
 ///
 /// # Errors
 ///
-/// Will return an `Err` if collecting, memory mapping and parsing of osh files fails.
-pub fn load_sorted() -> anyhow::Result<Vec<Event>> {
-    let oshs = osh_files(&Kind::Rmp)?;
-    let osh_files: Vec<File> = oshs
+/// Will return an `Err` if collecting osh files fails.
+pub fn load_streaming_timed<'a>(
+    home: &OshHome,
+    arena: &'a Arena,
+    mut emit: impl FnMut(EventRef<'a>) -> bool,
+    mut report: impl FnMut(LoadError),
+    timings: &Timings,
+) -> anyhow::Result<HashMap<PathBuf, u64>> {
+    let mut first = true;
+    let mut emit = |event| {
+        if first {
+            first = false;
+            timings.mark_once("first event");
+        }
+        emit(event)
+    };
+    let (tombstones, oshs) = timings.measure("discovery", || -> anyhow::Result<_> {
+        let tombstones = Tombstones::load(home, &mut report)?;
+        let mut oshs: Vec<(PathBuf, Arc<str>)> = Vec::new();
+        for kind in Kind::HISTORY {
+            oshs.extend(osh_files(home, &kind)?);
+        }
+        oshs.sort();
+        Ok((tombstones, oshs))
+    })?;
+
+    let cache = Cache::new(home.cache_dir());
+    let cached = timings.measure("cache lookup", || cache.load(&oshs, &mut report));
+    if let Some((cached, offsets)) = cached
+        && let Ok(events) = arena.keep(cached).events()
+    {
+        for &event in events.iter().rev() {
+            if !tombstones.covers(event) && !emit(event) {
+                break;
+            }
+        }
+        return Ok(offsets);
+    }
+
+    let mut seen = HashSet::new();
+    // borrowed like the emitted events, the cache is written from the mapped files
+    let mut loaded = Vec::new();
+    let mut complete = true;
+    let mut failed = HashSet::new();
+    let mut offsets = merge_files(
+        &oshs,
+        arena,
+        |event| {
+            if !seen.insert(event.id()) {
+                return true;
+            }
+            loaded.push(event);
+            if tombstones.covers(event) || emit(event) {
+                return true;
+            }
+            complete = false;
+            false
+        },
+        |error| {
+            failed.insert(error.path.clone());
+            report(error);
+        },
+        timings,
+    );
+    if complete {
+        let mut cached = offsets.clone();
+        cached.retain(|path, _| !failed.contains(path));
+        loaded.reverse();
+        // the cache is only an optimization, the next load tries again
+        let _ = timings.measure("cache rebuild", || cache.rebuild(&oshs, &cached, loaded));
+    }
+    // compressed files aren't appended to
+    offsets.retain(|path, _| Kind::of(path) == Some(Kind::Rmp));
+    Ok(offsets)
+}
+
+/// modification time of `file` in milliseconds, `i64::MAX` if unknown
+pub(crate) fn modified_millis(file: &File) -> i64 {
+    file.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .and_then(|since_epoch| i64::try_from(since_epoch.as_millis()).ok())
+        .unwrap_or(i64::MAX)
+}
+
+/// decode the events of the mapped file at `path` tagged with `label`, a compressed file is
+/// decompressed into `decompressed` first
+fn decode_file<'a>(
+    path: &Path,
+    label: &'a Arc<str>,
+    data: &'a Mmap,
+    decompressed: &'a OnceLock<std::io::Result<Vec<u8>>>,
+) -> std::io::Result<Vec<EventRef<'a>>> {
+    let data = if Kind::of(path) == Some(Kind::RmpZst) {
+        decompressed
+            .get_or_init(|| zstd::decode_all(&data[..]))
+            .as_deref()
+            .map_err(|e| std::io::Error::new(e.kind(), e.to_string()))?
+    } else {
+        &data[..]
+    };
+    let mut events = rmp::load_event_refs_parallel(data)?;
+    for event in &mut events {
+        event.root = Some(label);
+    }
+    Ok(events)
+}
+
+/// the newer endtime of the first and last event of an uncompressed history file, which covers
+/// files that are appended to or sorted
+fn outer_endtime(path: &Path, data: &[u8]) -> Option<i64> {
+    if Kind::of(path) != Some(Kind::Rmp) {
+        return None;
+    }
+    let (first, last) = rmp::outer_records(data).ok()??;
+    [first, last]
+        .into_iter()
+        .filter_map(|record| Some(rmp::load_event_refs(record).ok()?.first()?.endtime))
+        .max()
+}
+
+/// decode `files` in parallel, sort each file on its own and k-way merge them newest first into
+/// `emit`. Events borrow from the mapped (or decompressed) files kept in `arena` and are tagged
+/// with the root label of their file.
+///
+/// Events are appended after a command finished, so the modification time of a file bounds the
+/// endtimes in it. Files are decoded newest first and all events newer than the
+/// files still decoding are emitted right away: the newest events are available before old
+/// archives finished loading. The bound doesn't hold with clock skew between machines or for
+/// copies that kept an older modification time, so it is raised to the endtime of the first and
+/// last event of uncompressed files (read without decoding the rest). Compressed files are written
+/// by `archive` and `convert` and trusted. Events of a file that is newer in the middle than at
+/// both ends and older than its modification time can still be emitted late, [`load_sorted`]
+/// sorts once more.
+fn merge_files<'a>(
+    files: &[(PathBuf, Arc<str>)],
+    arena: &'a Arena,
+    mut emit: impl FnMut(EventRef<'a>) -> bool,
+    mut report: impl FnMut(LoadError),
+    timings: &Timings,
+) -> HashMap<PathBuf, u64> {
+    let mut opened = Vec::with_capacity(files.len());
+    timings.measure("mmap", || {
+        for (path, label) in files {
+            match File::open(path).and_then(|file| Ok((modified_millis(&file), Mmap::new(&file)?)))
+            {
+                Ok((modified, data)) => {
+                    let bound =
+                        outer_endtime(path, &data).map_or(modified, |newest| modified.max(newest));
+                    let label: &'a Arc<str> = arena.keep(Arc::clone(label));
+                    opened.push((path, label, bound, arena.keep(data)));
+                }
+                Err(e) => report(LoadError::new(path, e)),
+            }
+        }
+    });
+    let merge_started = std::time::Instant::now();
+    // upper bound of the endtimes per file
+    let bounds: Vec<i64> = opened.iter().map(|(_, _, bound, _)| *bound).collect();
+    let offsets = opened
+        .iter()
+        .map(|(path, _, _, data)| ((*path).clone(), data.len() as u64))
+        .collect();
+    let (paths, oshs_data): (Vec<&PathBuf>, Vec<(&'a Arc<str>, &'a Mmap)>) = opened
         .into_iter()
-        .map(File::open)
-        .collect::<Result<Vec<_>, _>>()?;
-    let oshs_data: Vec<&[u8]> = osh_files.iter().map(mmap).collect();
-    let all: Vec<Vec<Event>> = oshs_data
-        .par_iter()
-        .map(|data| rmp::load_osh_events(data))
-        .collect::<Result<Vec<_>, _>>()?;
+        .map(|(path, label, _, data)| (path, (label, data)))
+        .unzip();
 
-    let mut all_items: Vec<Event> = all.into_iter().flatten().collect();
-    all_items.par_sort_unstable_by(|a, b| b.cmp(a));
-    Ok(all_items)
+    let mut order: Vec<usize> = (0..paths.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(bounds.get(i)));
+    // compressed files are decompressed by the worker decoding them, events borrow from here
+    let decompressed: &'a Vec<OnceLock<std::io::Result<Vec<u8>>>> =
+        arena.keep(paths.iter().map(|_| OnceLock::new()).collect());
+
+    std::thread::scope(|scope| {
+        let (tx_file, rx_file) = crossbeam_channel::unbounded();
+        let (order, oshs_data, paths) = (&order, &oshs_data, &paths);
+        scope.spawn(move || {
+            order.par_iter().for_each_with(tx_file, |tx_file, &i| {
+                let started = std::time::Instant::now();
+                let events = match (paths.get(i), oshs_data.get(i), decompressed.get(i)) {
+                    (Some(path), Some(&(label, data)), Some(slot)) => {
+                        decode_file(path, label, data, slot)
+                    }
+                    _ => Ok(vec![]),
+                };
+                if timings.is_enabled()
+                    && let (Some(path), Ok(events)) = (paths.get(i), &events)
+                {
+                    let name = format!("decode {} ({} events)", path.display(), events.len());
+                    timings.record(name, started.elapsed());
+                }
+                // ascending, so the newest event can be popped off the end
+                let events = events.map(|mut events| {
+                    events.sort_by_key(|event| event.endtime);
+                    events
+                });
+                let _ = tx_file.send((i, events));
+            });
+        });
+
+        let mut sorted: Vec<Option<Vec<EventRef<'a>>>> = vec![None; paths.len()];
+        // (endtime of the newest remaining event, file index)
+        let mut heads: BinaryHeap<(i64, usize)> = BinaryHeap::new();
+        let mut pending = order.iter().peekable();
+        loop {
+            while pending
+                .next_if(|&&i| sorted.get(i).is_some_and(Option::is_some))
+                .is_some()
+            {}
+            let bound = pending.peek().and_then(|&&i| bounds.get(i));
+
+            while let Some(&(endtime, i)) = heads.peek() {
+                if bound.is_some_and(|bound| endtime < *bound) {
+                    break;
+                }
+                heads.pop();
+                let Some(events) = sorted.get_mut(i).and_then(|slot| slot.as_mut()) else {
+                    continue;
+                };
+                let Some(event) = events.pop() else {
+                    continue;
+                };
+                if let Some(next) = events.last() {
+                    heads.push((next.endtime, i));
+                }
+                if !emit(event) {
+                    return;
+                }
+            }
+
+            let Ok((i, events)) = rx_file.recv() else {
+                return;
+            };
+            let events = events.unwrap_or_else(|e| {
+                if let Some(path) = paths.get(i) {
+                    report(LoadError::new(path, e));
+                }
+                vec![]
+            });
+            if let Some(newest) = events.last() {
+                heads.push((newest.endtime, i));
+            }
+            if let Some(slot) = sorted.get_mut(i) {
+                *slot = Some(events);
+            }
+        }
+    });
+    timings.record("decode and merge", merge_started.elapsed());
+
+    offsets
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::Write;
+    use std::os::unix::fs::MetadataExt;
 
     use tempfile::TempDir;
 
     use super::*;
-
-    #[test]
-    fn mmap_reads_file_contents() {
-        let mut file = tempfile::tempfile().unwrap();
-        let data = b"hello mmap";
-        file.write_all(data).unwrap();
-        let mapped = mmap(&file);
-        assert_eq!(mapped, data);
-    }
+    use crate::testing::{append, event, write_events};
 
     #[test]
     fn discover_mixed_extensions() {
@@ -138,6 +698,499 @@ mod tests {
         assert!(found.is_empty());
     }
 
+    /// `paths` all labeled `local`
+    fn labeled(paths: &[PathBuf]) -> Vec<(PathBuf, Arc<str>)> {
+        paths
+            .iter()
+            .map(|path| (path.clone(), Arc::from("local")))
+            .collect()
+    }
+
+    fn merged(paths: &[PathBuf]) -> Vec<i64> {
+        let mut endtimes = Vec::new();
+        merge_files(
+            &labeled(paths),
+            &Arena::new(),
+            |event| {
+                endtimes.push(event.endtime);
+                true
+            },
+            |error| panic!("{error}"),
+            &Timings::default(),
+        );
+        endtimes
+    }
+
+    #[test]
+    fn merge_files_newest_first() {
+        let dir = TempDir::new().unwrap();
+        let paths = [dir.path().join("a.bosh"), dir.path().join("b.bosh")];
+        // long running commands are appended out of order
+        write_events(&paths[0], &[1, 4, 3, 9]);
+        write_events(&paths[1], &[2, 5, 8, 6, 7]);
+
+        assert_eq!(merged(&paths), vec![9, 8, 7, 6, 5, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn merge_files_respects_old_modification_times() {
+        let dir = TempDir::new().unwrap();
+        let paths = [
+            dir.path().join("archive.bosh"),
+            dir.path().join("local.bosh"),
+        ];
+        write_events(&paths[0], &[1000, 3000, 2000]);
+        write_events(&paths[1], &[4000, 6000, 5000]);
+        File::options()
+            .write(true)
+            .open(&paths[0])
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(3))
+            .unwrap();
+
+        assert_eq!(merged(&paths), vec![6000, 5000, 4000, 3000, 2000, 1000]);
+    }
+
+    #[test]
+    fn merge_files_tolerates_clock_skew() {
+        let dir = TempDir::new().unwrap();
+        let paths = [
+            dir.path().join("skewed.bosh"),
+            dir.path().join("local.bosh"),
+        ];
+        // copied from a machine whose clock is ahead, keeping its (older) modification time
+        write_events(&paths[0], &[5000, 7000]);
+        write_events(&paths[1], &[4000, 6000]);
+        File::options()
+            .write(true)
+            .open(&paths[0])
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1))
+            .unwrap();
+
+        for _ in 0..10 {
+            assert_eq!(merged(&paths), vec![7000, 6000, 5000, 4000]);
+        }
+    }
+
+    #[test]
+    fn merge_files_reports_offsets() {
+        let dir = TempDir::new().unwrap();
+        let paths = [dir.path().join("a.bosh")];
+        write_events(&paths[0], &[1, 2]);
+
+        let offsets = merge_files(
+            &labeled(&paths),
+            &Arena::new(),
+            |_| true,
+            |error| panic!("{error}"),
+            &Timings::default(),
+        );
+        assert_eq!(
+            offsets[&paths[0]],
+            std::fs::metadata(&paths[0]).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn merge_files_handles_empty_files() {
+        let dir = TempDir::new().unwrap();
+        let paths = [dir.path().join("a.bosh"), dir.path().join("empty.bosh")];
+        write_events(&paths[0], &[1, 2]);
+        write_events(&paths[1], &[]);
+
+        assert_eq!(merged(&paths), vec![2, 1]);
+    }
+
+    #[test]
+    fn merge_files_stops_early() {
+        let dir = TempDir::new().unwrap();
+        let paths = [dir.path().join("a.bosh")];
+        write_events(&paths[0], &[1, 2, 3]);
+
+        let mut endtimes = Vec::new();
+        merge_files(
+            &labeled(&paths),
+            &Arena::new(),
+            |event| {
+                endtimes.push(event.endtime);
+                endtimes.len() < 2
+            },
+            |error| panic!("{error}"),
+            &Timings::default(),
+        );
+        assert_eq!(endtimes, vec![3, 2]);
+    }
+
+    #[test]
+    fn merge_files_reports_broken_files() {
+        let dir = TempDir::new().unwrap();
+        let paths = [
+            dir.path().join("broken.bosh"),
+            dir.path().join("missing.bosh"),
+            dir.path().join("good.bosh"),
+        ];
+        std::fs::write(&paths[0], b"broken").unwrap();
+        write_events(&paths[2], &[1, 2]);
+
+        let mut endtimes = Vec::new();
+        let mut errors = Vec::new();
+        merge_files(
+            &labeled(&paths),
+            &Arena::new(),
+            |event| {
+                endtimes.push(event.endtime);
+                true
+            },
+            |error| errors.push(error),
+            &Timings::default(),
+        );
+        assert_eq!(endtimes, vec![2, 1]);
+        errors.sort_by(|a, b| a.path.cmp(&b.path));
+        let failed: Vec<&PathBuf> = errors.iter().map(|error| &error.path).collect();
+        assert_eq!(failed, vec![&paths[0], &paths[1]]);
+    }
+
+    #[test]
+    fn merge_files_labels_events() {
+        let dir = TempDir::new().unwrap();
+        let paths = [dir.path().join("a.bosh"), dir.path().join("b.bosh")];
+        write_events(&paths[0], &[1]);
+        write_events(&paths[1], &[2]);
+        let files = [
+            (paths[0].clone(), Arc::from("mine")),
+            (paths[1].clone(), Arc::from("team")),
+        ];
+
+        let mut labels = Vec::new();
+        merge_files(
+            &files,
+            &Arena::new(),
+            |event| {
+                labels.push(event.root.cloned());
+                true
+            },
+            |error| panic!("{error}"),
+            &Timings::default(),
+        );
+        assert_eq!(
+            labels,
+            vec![Some(Arc::from("team")), Some(Arc::from("mine"))]
+        );
+    }
+
+    fn root(root: &str) -> Root {
+        root.parse().unwrap()
+    }
+
+    #[test]
+    fn root_from_str() {
+        assert_eq!(
+            root("team=/mnt/team/osh"),
+            Root {
+                label: "team".to_string(),
+                path: PathBuf::from("/mnt/team/osh"),
+            }
+        );
+        assert_eq!(root("/home/me/.osh").label, ".osh");
+        assert_eq!(root("=/a=b").path, Path::new("=/a=b"));
+    }
+
+    #[test]
+    fn osh_home_defaults_to_local_bosh() {
+        let home = OshHome::new(vec![root("/tmp/osh")], None).unwrap();
+        assert_eq!(home.roots, vec![root("/tmp/osh")]);
+        assert_eq!(home.file, Path::new("/tmp/osh/local.bosh"));
+
+        let home = OshHome::new(
+            vec![root("/tmp/osh"), root("team=/tmp/team")],
+            Some(PathBuf::from("/tmp/work.bosh")),
+        )
+        .unwrap();
+        assert_eq!(home.file, Path::new("/tmp/work.bosh"));
+        assert_eq!(home.labels(), vec!["osh", "team"]);
+    }
+
+    #[test]
+    fn osh_files_dedupe_across_roots() {
+        let mine = TempDir::new().unwrap();
+        let team = TempDir::new().unwrap();
+        std::fs::File::create(mine.path().join("local.bosh")).unwrap();
+        std::fs::File::create(team.path().join("ci.bosh")).unwrap();
+        // the team folder is also linked into the personal root
+        std::os::unix::fs::symlink(team.path(), mine.path().join("team")).unwrap();
+
+        let roots = vec![
+            Root {
+                label: "team".to_string(),
+                path: team.path().to_path_buf(),
+            },
+            Root {
+                label: "mine".to_string(),
+                path: mine.path().to_path_buf(),
+            },
+        ];
+        let home = OshHome::new(roots, None).unwrap();
+        let found = osh_files(&home, &Kind::Rmp).unwrap();
+        assert_eq!(found.len(), 2);
+        let ci = team.path().join("ci.bosh").canonicalize().unwrap();
+        assert_eq!(found[&ci].as_ref(), "team");
+        let local = mine.path().join("local.bosh").canonicalize().unwrap();
+        assert_eq!(found[&local].as_ref(), "mine");
+
+        let skipped = discover(&home, &Kind::Rmp).unwrap().skipped;
+        assert_eq!(
+            skipped,
+            vec![(
+                mine.path().join("team/ci.bosh"),
+                SkipReason::Duplicate { of: ci }
+            )]
+        );
+    }
+
+    #[test]
+    fn osh_files_include_file_outside_root() {
+        let root = TempDir::new().unwrap();
+        let other = TempDir::new().unwrap();
+        std::fs::File::create(root.path().join("local.bosh")).unwrap();
+        let file = other.path().join("work.bosh");
+        std::fs::File::create(&file).unwrap();
+
+        let roots = vec![Root {
+            label: "local".to_string(),
+            path: root.path().to_path_buf(),
+        }];
+        let home = OshHome::new(roots, Some(file.clone())).unwrap();
+        let found = osh_files(&home, &Kind::Rmp).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[&file.canonicalize().unwrap()].as_ref(), "local");
+        assert!(osh_files(&home, &Kind::JsonLines).unwrap().is_empty());
+    }
+
+    #[test]
+    fn load_sorted_drops_copies() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("active")).unwrap();
+        write_events(&dir.path().join("laptop.bosh"), &[1, 2, 3]);
+        // the same host copied twice
+        std::fs::copy(
+            dir.path().join("laptop.bosh"),
+            dir.path().join("active/laptop.bosh"),
+        )
+        .unwrap();
+        write_events(&dir.path().join("active/laptop-copy.bosh"), &[2, 3, 4]);
+
+        let roots = vec![Root {
+            label: "local".to_string(),
+            path: dir.path().to_path_buf(),
+        }];
+        let events = load_sorted(&OshHome::new(roots, None).unwrap()).unwrap();
+        let endtimes: Vec<i64> = events.iter().map(|event| event.endtime).collect();
+        assert_eq!(endtimes, vec![4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn load_sorted_skips_tombstoned_events() {
+        let root = TempDir::new().unwrap();
+        let other = TempDir::new().unwrap();
+        write_events(&root.path().join("laptop.bosh"), &[1, 2, 3]);
+        let file = other.path().join("work.bosh");
+        write_events(&file, &[4]);
+
+        let removed = event("cmd 2", 2);
+        // tombstones next to a file outside of the roots are loaded as well
+        tombstone::append(
+            &tombstone::sidecar(&file),
+            [tombstone::Tombstone::from(&removed)],
+        )
+        .unwrap();
+
+        let roots = vec![Root {
+            label: "local".to_string(),
+            path: root.path().to_path_buf(),
+        }];
+        let home = OshHome::new(roots, Some(file)).unwrap();
+        let endtimes: Vec<i64> = load_sorted(&home)
+            .unwrap()
+            .iter()
+            .map(|event| event.endtime)
+            .collect();
+        assert_eq!(endtimes, vec![4, 3, 1]);
+    }
+
+    #[test]
+    fn load_sorted_uses_cache() {
+        let root = TempDir::new().unwrap();
+        let file = root.path().join("laptop.bosh");
+        write_events(&file, &[1, 3]);
+        let roots = vec![Root {
+            label: "local".to_string(),
+            path: root.path().to_path_buf(),
+        }];
+        let home = OshHome::new(roots, None).unwrap();
+        let endtimes = || -> Vec<i64> {
+            load_sorted(&home)
+                .unwrap()
+                .iter()
+                .map(|event| event.endtime)
+                .collect()
+        };
+        assert_eq!(endtimes(), vec![3, 1]);
+        assert!(home.cache_dir().is_dir());
+
+        append(&file, &[event("cmd 2", 2)]);
+        assert_eq!(endtimes(), vec![3, 2, 1]);
+
+        // tombstones apply to cached events
+        let removed = load_sorted(&home).unwrap().remove(0);
+        tombstone::append(
+            &tombstone::sidecar(&home.file),
+            [tombstone::Tombstone::from(&removed)],
+        )
+        .unwrap();
+        assert_eq!(endtimes(), vec![2, 1]);
+    }
+
+    #[test]
+    fn history_without_archive_is_cached_separately() {
+        let root = TempDir::new().unwrap();
+        std::fs::create_dir(root.path().join("archive")).unwrap();
+        write_events(&root.path().join("archive/old.bosh"), &[1]);
+        write_events(&root.path().join("local.bosh"), &[2]);
+        let roots = vec![Root::from_path(root.path().to_path_buf())];
+        let mut home = OshHome::new(roots, None).unwrap();
+        let endtimes = |home: &OshHome| -> Vec<i64> {
+            load_sorted(home)
+                .unwrap()
+                .iter()
+                .map(|event| event.endtime)
+                .collect()
+        };
+        let all = home.cache_dir();
+
+        assert_eq!(endtimes(&home), vec![2, 1]);
+        let events = std::fs::metadata(all.join("events")).unwrap().ino();
+        home.archive = Some(root.path().join("archive"));
+        assert_eq!(endtimes(&home), vec![2]);
+        assert_ne!(home.cache_dir(), all);
+        home.archive = None;
+        assert_eq!(endtimes(&home), vec![2, 1]);
+        // loaded from the cache, not rebuilt
+        assert_eq!(std::fs::metadata(all.join("events")).unwrap().ino(), events);
+    }
+
+    #[test]
+    fn conflict_copies_are_detected() {
+        let originals = [
+            (
+                "a/host.sync-conflict-20261001-120000-ABCDEFG.bosh",
+                "a/host.bosh",
+            ),
+            ("a/host (conflicted copy).bosh", "a/host.bosh"),
+            ("host (conflicted copy 2026-10-01 120000).bosh", "host.bosh"),
+            (
+                "host (Someone's conflicted copy 2026-10-01).tomb",
+                "host.tomb",
+            ),
+            (
+                "a/host.sync-conflict-20261001-120000-ABCDEFG.bosh.zst",
+                "a/host.bosh.zst",
+            ),
+            ("host (conflicted copy).bosh.zst", "host.bosh.zst"),
+        ];
+        for (copy, original) in originals {
+            assert_eq!(
+                conflict_original(Path::new(copy)),
+                Some(PathBuf::from(original))
+            );
+        }
+        for path in [
+            "host.bosh",
+            "host (1).bosh",
+            "sync-conflict.bosh",
+            "host.bosh.zst",
+            "host (conflicted copy).txt",
+        ] {
+            assert_eq!(conflict_original(Path::new(path)), None);
+        }
+    }
+
+    #[test]
+    fn conflict_copies_are_merged_with_original() {
+        let dir = TempDir::new().unwrap();
+        write_events(&dir.path().join("host.bosh"), &[1, 2, 3]);
+        let copy = dir
+            .path()
+            .join("host.sync-conflict-20261001-120000-ABC.bosh");
+        write_events(&copy, &[1, 2, 4]);
+
+        let roots = vec![Root {
+            label: "local".to_string(),
+            path: dir.path().to_path_buf(),
+        }];
+        let home = OshHome::new(roots, None).unwrap();
+        let conflicts = discover(&home, &Kind::Rmp).unwrap().conflicts;
+        let root = dir.path().canonicalize().unwrap();
+        assert_eq!(
+            conflicts,
+            HashMap::from([(copy.canonicalize().unwrap(), root.join("host.bosh"))])
+        );
+        let endtimes: Vec<i64> = load_sorted(&home)
+            .unwrap()
+            .iter()
+            .map(|event| event.endtime)
+            .collect();
+        assert_eq!(endtimes, vec![4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn discover_skips_archive() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("archive")).unwrap();
+        std::fs::File::create(dir.path().join("archive/old.bosh")).unwrap();
+        std::fs::File::create(dir.path().join("local.bosh")).unwrap();
+
+        let roots = vec![Root {
+            label: "local".to_string(),
+            path: dir.path().to_path_buf(),
+        }];
+        let mut home = OshHome::new(roots, None).unwrap();
+        assert_eq!(osh_files(&home, &Kind::Rmp).unwrap().len(), 2);
+
+        home.archive = Some(dir.path().join("archive"));
+        let discovery = discover(&home, &Kind::Rmp).unwrap();
+        assert_eq!(discovery.files.len(), 1);
+        assert_eq!(
+            discovery.skipped,
+            vec![(dir.path().join("archive/old.bosh"), SkipReason::Archived)]
+        );
+    }
+
+    #[test]
+    fn discover_skips_oshignore_matches() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("archive")).unwrap();
+        std::fs::write(dir.path().join(".oshignore"), "archive/\n").unwrap();
+        std::fs::File::create(dir.path().join("archive/old.bosh")).unwrap();
+        std::fs::File::create(dir.path().join("local.bosh")).unwrap();
+
+        let roots = vec![Root {
+            label: "local".to_string(),
+            path: dir.path().to_path_buf(),
+        }];
+        let discovery = discover(&OshHome::new(roots, None).unwrap(), &Kind::Rmp).unwrap();
+        assert_eq!(discovery.files.len(), 1);
+        assert_eq!(
+            discovery.skipped,
+            vec![(
+                dir.path().join("archive/old.bosh"),
+                SkipReason::Ignored {
+                    pattern: "archive/".to_string()
+                }
+            )]
+        );
+    }
+
     #[test]
     fn discover_nested() {
         let dir = TempDir::new().unwrap();
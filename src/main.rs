@@ -1,11 +1,25 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, path::PathBuf, time::Instant};
 
 use clap::{Parser, Subcommand};
-use osh_oxy::{commands, ui::EventFilter};
+use osh_oxy::{
+    OshHome, Root, commands,
+    config::{Age, Config, Retention},
+    event::Event,
+    output::OutputFormat,
+    timings::Timings,
+    ui::{EventFilter, FilterContext},
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    /// history root as `path` or `label=path`, can be repeated, defaults to `$OSH_HOME` or
+    /// `~/.osh`
+    #[arg(long, global = true)]
+    root: Vec<Root>,
+    /// history file new events are appended to, defaults to `local.bosh` in the first root
+    #[arg(long, global = true)]
+    file: Option<PathBuf>,
     #[command(subcommand)]
     command: Command,
 }
@@ -13,14 +27,15 @@ struct Args {
 #[derive(Subcommand, Debug)]
 #[clap(rename_all = "kebab_case")]
 enum Command {
+    /// record a finished command, timestamps are in milliseconds since the epoch
     AppendEvent {
-        #[arg(long)]
+        #[arg(long, value_parser = commands::append_event::parse_timestamp)]
         starttime: i64,
         #[arg(long)]
         command: String,
         #[arg(long)]
         folder: String,
-        #[arg(long)]
+        #[arg(long, value_parser = commands::append_event::parse_timestamp)]
         endtime: i64,
         #[arg(long)]
         exit_code: i16,
@@ -28,9 +43,79 @@ enum Command {
         machine: String,
         #[arg(long)]
         session: String,
+        /// convert timestamps that look like seconds to milliseconds instead of rejecting them
+        #[arg(long)]
+        fix_units: bool,
+    },
+    Cat {
+        #[arg(long, default_value_t)]
+        output: OutputFormat,
+        /// line template with placeholders `{command}`, `{folder}`, `{exit}`, `{duration}`,
+        /// `{machine}`, `{session}`, `{root}`, `{ago}` and `{start}`/`{end}` (optionally
+        /// `{start:%Y-%m-%d}`)
+        #[arg(long, default_value = commands::cat::DEFAULT_FORMAT)]
+        format: String,
+        /// separate entries with `\0` instead of newlines
+        #[arg(long)]
+        null: bool,
+        #[arg(long)]
+        filter: Vec<EventFilter>,
+        /// folder for the `folder` filter (defaults to the current directory)
+        #[arg(long)]
+        folder: Option<String>,
+        #[arg(long)]
+        session_id: Option<String>,
+        /// root label for the `root` filter
+        #[arg(long)]
+        root_label: Option<String>,
+        /// print how long loading, filtering and writing took to stderr
+        #[arg(long)]
+        timings: bool,
+    },
+    /// move old events to the archive folder
+    Archive {
+        /// archive events that ended longer ago, e.g. `6m` or `1y` (defaults to
+        /// `retention.older-than` in the config)
+        #[arg(long)]
+        older_than: Option<Age>,
+        /// only report what would be archived
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// sort history files by endtime and drop copies of events, optionally merging them into one
+    Compact {
+        /// history files or folders to compact (defaults to the history files in the first root)
+        paths: Vec<PathBuf>,
+        /// merge the files into this archive and delete them, the active history file is kept
+        /// unless listed
+        #[arg(long)]
+        into: Option<PathBuf>,
+        /// only report the sizes before and after
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// convert history files, by default all json lines files to rmp
+    Convert {
+        /// history files to convert
+        paths: Vec<PathBuf>,
+        /// compress with zstd (`.bosh.zst`), for archives that are rarely read
+        #[arg(long)]
+        compress: bool,
+    },
+    /// list the history files that are loaded, and why others are skipped
+    Files {},
+    /// fold conflict copies of sync tools back into their original history files
+    ResolveConflicts {
+        /// only report what would be merged
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// redact secrets in existing history files
+    Scrub {
+        /// only report what would be redacted
+        #[arg(long)]
+        dry_run: bool,
     },
-    Cat {},
-    Convert {},
     Search {
         #[arg(long, default_value = "")]
         query: String,
@@ -42,14 +127,65 @@ enum Command {
         filter: Vec<EventFilter>,
         #[arg(long)]
         show_score: bool,
+        #[arg(long, default_value_t)]
+        output: OutputFormat,
+        /// root label for the `root` filter, cycle through the roots with ctrl-r
+        #[arg(long)]
+        root_label: Option<String>,
+        /// only list the events marked for removal instead of removing them
+        #[arg(long)]
+        dry_run: bool,
+        /// load archived history (defaults to `retention.search-archives` in the config)
+        #[arg(long, overrides_with = "no_archives")]
+        archives: bool,
+        /// skip archived history
+        #[arg(long, overrides_with = "archives")]
+        no_archives: bool,
+        /// print when the phases of the startup ended to stderr after closing the TUI
+        #[arg(long)]
+        timings: bool,
     },
 }
 
+/// skip the archive folder when loading history for search and files, unless `archives` (default
+/// `retention.search_archives`) are loaded
+fn skip_archives(home: &mut OshHome, retention: &Retention, archives: Option<bool>) {
+    if !archives.unwrap_or(retention.search_archives) {
+        home.archive = Some(retention.archive_dir(home));
+    }
+}
+
 fn main() -> anyhow::Result<()> {
+    let start = Instant::now();
     let args = Args::parse();
+    let mut home = OshHome::new(args.root, args.file)?;
+    let config = Config::load(&home.config_path())?;
 
     match args.command {
-        Command::Cat {} => commands::cat::invoke()?,
+        Command::Cat {
+            output,
+            format,
+            null,
+            filter,
+            folder,
+            session_id,
+            root_label,
+            timings,
+        } => {
+            let folder = match folder {
+                Some(folder) => folder,
+                None => std::env::current_dir()?.to_string_lossy().into_owned(),
+            };
+            let filters = HashSet::from_iter(filter);
+            let context = FilterContext {
+                folder,
+                session_id,
+                root: root_label,
+                exclude: config.exclude,
+            };
+            let timings = Timings::since(start, timings);
+            commands::cat::invoke(&home, output, &format, null, &filters, &context, &timings)?;
+        }
         Command::AppendEvent {
             starttime,
             command,
@@ -58,19 +194,66 @@ fn main() -> anyhow::Result<()> {
             exit_code,
             machine,
             session,
+            fix_units,
         } => commands::append_event::invoke(
-            starttime, &command, &folder, endtime, exit_code, &machine, &session,
+            &home.file,
+            Event {
+                timestamp_millis: starttime,
+                command,
+                endtime,
+                exit_code,
+                folder: folder.into(),
+                machine: machine.into(),
+                session: session.into(),
+                root: None,
+            },
+            &config.exclude,
+            fix_units,
         )?,
-        Command::Convert {} => commands::convert::invoke()?,
+        Command::Archive {
+            older_than,
+            dry_run,
+        } => commands::archive::invoke(&home, &config.retention, older_than, dry_run)?,
+        Command::Compact {
+            paths,
+            into,
+            dry_run,
+        } => commands::compact::invoke(&home, paths, into.as_deref(), dry_run)?,
+        Command::Convert { paths, compress } => commands::convert::invoke(&home, paths, compress)?,
+        Command::Files {} => {
+            skip_archives(&mut home, &config.retention, None);
+            commands::files::invoke(&home)?;
+        }
+        Command::ResolveConflicts { dry_run } => {
+            commands::resolve_conflicts::invoke(&home, dry_run)?;
+        }
+        Command::Scrub { dry_run } => commands::scrub::invoke(&home, dry_run)?,
         Command::Search {
             query,
             folder,
             session_id,
             filter,
             show_score,
+            output,
+            root_label,
+            dry_run,
+            archives,
+            no_archives,
+            timings,
         } => {
+            let archives = (archives || no_archives).then_some(archives);
+            skip_archives(&mut home, &config.retention, archives);
             let filters = HashSet::from_iter(filter);
-            commands::search::invoke(&query, &folder, session_id, filters, show_score);
+            let context = FilterContext {
+                folder,
+                session_id,
+                root: root_label,
+                exclude: config.exclude,
+            };
+            let timings = Timings::since(start, timings);
+            commands::search::invoke(
+                &home, &query, context, filters, show_score, output, dry_run, &timings,
+            )?;
         }
     }
 
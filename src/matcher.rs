@@ -266,7 +266,8 @@ impl FuzzyEngine {
         }
     }
 
-    /// Match an OR group: returns first matching term's (score, indices), or None if no term matches.
+    /// Match an OR group: returns first matching term's (score, indices), or None if no term
+    /// matches.
     fn match_or_group(&self, line: &str, group: &OrGroup) -> Option<(i64, Vec<usize>)> {
         group
             .terms
@@ -0,0 +1,126 @@
+//! Read-only memory maps of history files.
+use std::{fs::File, io::Read, ops::Deref, os::fd::AsRawFd, ptr::NonNull};
+
+/// Contents of a file, memory mapped if possible and read into a buffer otherwise. The mapping is
+/// removed on drop.
+///
+/// Only the length at the time of [`Mmap::new`] is mapped, data appended later is not visible.
+/// Truncating a file while it is mapped raises `SIGBUS` when the lost pages are accessed, which
+/// is why files must be replaced atomically (write a new file and rename it over the old one)
+/// instead of rewritten in place.
+pub struct Mmap {
+    inner: Inner,
+}
+
+enum Inner {
+    Mapped { ptr: NonNull<u8>, len: usize },
+    Buffer(Vec<u8>),
+}
+
+// the mapping is read-only and owned by `Mmap`
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// memory map `file`. Empty files can't be mapped and special files (pipes, devices, ...) have
+    /// no meaningful length, both are read into a buffer instead.
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if `statx`, memory mapping or reading fails.
+    pub fn new(file: &File) -> std::io::Result<Self> {
+        let metadata = file.metadata()?;
+        if !metadata.is_file() || metadata.len() == 0 {
+            let mut buffer = Vec::new();
+            (&*file).read_to_end(&mut buffer)?;
+            return Ok(Self {
+                inner: Inner::Buffer(buffer),
+            });
+        }
+
+        let len = libc::size_t::try_from(metadata.len())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::FileTooLarge, e))?;
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        // only a hint, reading works regardless
+        let _ = unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+        let ptr =
+            NonNull::new(ptr.cast::<u8>()).ok_or(std::io::Error::other("mmap returned null"))?;
+
+        Ok(Self {
+            inner: Inner::Mapped { ptr, len },
+        })
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.inner {
+            Inner::Mapped { ptr, len } => unsafe { std::slice::from_raw_parts(ptr.as_ptr(), *len) },
+            Inner::Buffer(buffer) => buffer,
+        }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if let Inner::Mapped { ptr, len } = self.inner {
+            unsafe {
+                libc::munmap(ptr.as_ptr().cast(), len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn mmap_reads_file_contents() {
+        let mut file = tempfile::tempfile().unwrap();
+        let data = b"hello mmap";
+        file.write_all(data).unwrap();
+        let mapped = Mmap::new(&file).unwrap();
+        assert!(matches!(mapped.inner, Inner::Mapped { .. }));
+        assert_eq!(&*mapped, data);
+    }
+
+    #[test]
+    fn mmap_empty_file() {
+        let file = tempfile::tempfile().unwrap();
+        let mapped = Mmap::new(&file).unwrap();
+        assert!(mapped.is_empty());
+    }
+
+    #[test]
+    fn mmap_special_file() {
+        let file = File::open("/dev/null").unwrap();
+        let mapped = Mmap::new(&file).unwrap();
+        assert!(mapped.is_empty());
+    }
+
+    #[test]
+    fn mmap_ignores_appends() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"hello").unwrap();
+        let mapped = Mmap::new(&file).unwrap();
+        file.write_all(b" world").unwrap();
+        assert_eq!(&*mapped, b"hello");
+    }
+}
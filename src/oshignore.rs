@@ -0,0 +1,118 @@
+//! `.oshignore` files: gitignore-style patterns for history files below a root that should not be
+//! loaded, e.g. `archive/` or `hosts/old-laptop.bosh`. Only the file directly in the root is read.
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use ignore::{
+    Match,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+
+/// name of the ignore file in a history root
+pub const FILE_NAME: &str = ".oshignore";
+
+/// Ignore patterns of one history root.
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    /// `root` with symlinks resolved, for paths reported by the watcher
+    canonical_root: Option<PathBuf>,
+    matcher: Gitignore,
+}
+
+impl IgnoreRules {
+    /// read `root/.oshignore`, a missing file ignores nothing
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if the file can't be read or contains invalid patterns.
+    pub fn load(root: &Path) -> anyhow::Result<Self> {
+        let mut builder = GitignoreBuilder::new(root);
+        let file = root.join(FILE_NAME);
+        if file.exists()
+            && let Some(e) = builder.add(&file)
+        {
+            return Err(anyhow!("{}: {e}", file.display()));
+        }
+        let matcher = builder
+            .build()
+            .map_err(|e| anyhow!("{}: {e}", file.display()))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            canonical_root: root.canonicalize().ok(),
+            matcher,
+        })
+    }
+
+    /// the pattern excluding `path`, `None` if it should be loaded or lives outside of the root
+    #[must_use]
+    pub fn ignored_by(&self, path: &Path) -> Option<String> {
+        let relative = path
+            .strip_prefix(&self.root)
+            .ok()
+            .or_else(|| path.strip_prefix(self.canonical_root.as_ref()?).ok())?;
+        match self.matcher.matched_path_or_any_parents(relative, false) {
+            Match::Ignore(glob) => Some(glob.original().to_string()),
+            Match::None | Match::Whitelist(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn rules(patterns: &str) -> (TempDir, IgnoreRules) {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join(FILE_NAME), patterns).unwrap();
+        let rules = IgnoreRules::load(dir.path()).unwrap();
+        (dir, rules)
+    }
+
+    #[test]
+    fn folders_and_files_are_ignored() {
+        let (dir, rules) = rules("archive/\nhosts/old-*.bosh\n");
+        let root = dir.path();
+        assert_eq!(
+            rules.ignored_by(&root.join("archive/2023/a.bosh")),
+            Some("archive/".to_string())
+        );
+        assert_eq!(
+            rules.ignored_by(&root.join("hosts/old-laptop.bosh")),
+            Some("hosts/old-*.bosh".to_string())
+        );
+        assert_eq!(rules.ignored_by(&root.join("hosts/new.bosh")), None);
+        assert_eq!(rules.ignored_by(&root.join("local.bosh")), None);
+    }
+
+    #[test]
+    fn negated_patterns_are_loaded() {
+        let (dir, rules) = rules("*.bosh\n!local.bosh\n");
+        assert_eq!(rules.ignored_by(&dir.path().join("local.bosh")), None);
+        assert!(rules.ignored_by(&dir.path().join("other.bosh")).is_some());
+    }
+
+    #[test]
+    fn canonical_paths_are_matched() {
+        let (dir, rules) = rules("archive/\n");
+        let canonical = dir.path().canonicalize().unwrap();
+        assert!(
+            rules
+                .ignored_by(&canonical.join("archive/a.bosh"))
+                .is_some()
+        );
+        assert_eq!(
+            rules.ignored_by(Path::new("/elsewhere/archive/a.bosh")),
+            None
+        );
+    }
+
+    #[test]
+    fn missing_file_ignores_nothing() {
+        let dir = TempDir::new().unwrap();
+        let rules = IgnoreRules::load(dir.path()).unwrap();
+        assert_eq!(rules.ignored_by(&dir.path().join("a.bosh")), None);
+    }
+}
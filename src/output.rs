@@ -0,0 +1,207 @@
+//! Structured output of [`Event`]s for downstream tools (see `--output` on `cat` and `search`).
+use std::{fmt::Display, io::Write, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::event::Event;
+
+pub mod template;
+
+#[derive(Debug, Clone, Copy, Default, Hash, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// human readable text (the default)
+    #[default]
+    Text,
+    /// a single JSON document
+    Json,
+    /// one JSON object per line
+    Jsonl,
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Jsonl => write!(f, "jsonl"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseOutputFormatError(String);
+
+impl Display for ParseOutputFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid output format: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseOutputFormatError {}
+
+impl FromStr for OutputFormat {
+    type Err = ParseOutputFormatError;
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => Err(ParseOutputFormatError(format.to_string())),
+        }
+    }
+}
+
+/// [`Event`] with derived fields as it is written in structured output.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct EventRecord<'a> {
+    #[serde(flatten)]
+    event: &'a Event,
+    /// `endtime - timestamp-millis`
+    duration_millis: i64,
+    /// start time as ISO 8601 (`None` if out of range)
+    start: Option<DateTime<Utc>>,
+    /// end time as ISO 8601 (`None` if out of range)
+    end: Option<DateTime<Utc>>,
+    /// label of the history root the event was loaded from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root: Option<&'a str>,
+}
+
+impl<'a> From<&'a Event> for EventRecord<'a> {
+    fn from(event: &'a Event) -> Self {
+        Self {
+            event,
+            duration_millis: event.endtime.saturating_sub(event.timestamp_millis),
+            start: DateTime::from_timestamp_millis(event.timestamp_millis),
+            end: DateTime::from_timestamp_millis(event.endtime),
+            root: event.root.as_deref(),
+        }
+    }
+}
+
+/// write `events` to `writer` as a JSON array ([`OutputFormat::Json`]) or as one object per line
+/// ([`OutputFormat::Jsonl`]).
+///
+/// # Errors
+///
+/// Will return an `Err` if `format` is [`OutputFormat::Text`], or serialisation or writing fails.
+pub fn write_events<'a, W: Write>(
+    mut writer: W,
+    format: OutputFormat,
+    events: impl IntoIterator<Item = &'a Event>,
+) -> anyhow::Result<()> {
+    let records = events.into_iter().map(EventRecord::from);
+    match format {
+        OutputFormat::Text => anyhow::bail!("text output is not structured"),
+        OutputFormat::Json => {
+            serde_json::to_writer(&mut writer, &records.collect::<Vec<_>>())?;
+            writeln!(writer)?;
+        }
+        OutputFormat::Jsonl => {
+            for record in records {
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// write a single `event` to `writer` as JSON object, in both structured formats.
+///
+/// # Errors
+///
+/// Will return an `Err` if `format` is [`OutputFormat::Text`], or serialisation or writing fails.
+pub fn write_event<W: Write>(
+    mut writer: W,
+    format: OutputFormat,
+    event: &Event,
+) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Text => anyhow::bail!("text output is not structured"),
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            serde_json::to_writer(&mut writer, &EventRecord::from(event))?;
+            writeln!(writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(command: &str) -> Event {
+        Event {
+            timestamp_millis: 1_000_000_000_000,
+            command: command.to_string(),
+            endtime: 1_000_000_001_500,
+            exit_code: 1,
+            folder: "/tmp".into(),
+            machine: "m".into(),
+            session: "s".into(),
+            root: None,
+        }
+    }
+
+    #[test]
+    fn jsonl_one_object_per_line() {
+        let events = [event("ls"), event("pwd")];
+        let mut buffer = Vec::new();
+        write_events(&mut buffer, OutputFormat::Jsonl, &events).unwrap();
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(buffer)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["command"], "ls");
+        assert_eq!(lines[1]["command"], "pwd");
+    }
+
+    #[test]
+    fn json_contains_derived_fields() {
+        let events = [event("ls")];
+        let mut buffer = Vec::new();
+        write_events(&mut buffer, OutputFormat::Json, &events).unwrap();
+
+        let value: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        let record = &value[0];
+        assert_eq!(record["exit-code"], 1);
+        assert_eq!(record["timestamp-millis"], 1_000_000_000_000_i64);
+        assert_eq!(record["duration-millis"], 1500);
+        assert_eq!(record["start"], "2001-09-09T01:46:40Z");
+        assert_eq!(record["end"], "2001-09-09T01:46:41.500Z");
+        assert!(record.get("root").is_none());
+    }
+
+    #[test]
+    fn json_contains_root_label() {
+        let mut event = event("ls");
+        event.root = Some(std::sync::Arc::from("team"));
+        let mut buffer = Vec::new();
+        write_event(&mut buffer, OutputFormat::Json, &event).unwrap();
+
+        let value: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(value["root"], "team");
+    }
+
+    #[test]
+    fn text_is_not_structured() {
+        assert!(write_events(Vec::new(), OutputFormat::Text, &[event("ls")]).is_err());
+        assert!(write_event(Vec::new(), OutputFormat::Text, &event("ls")).is_err());
+    }
+
+    #[test]
+    fn output_format_from_str_roundtrip() {
+        for format in [OutputFormat::Text, OutputFormat::Json, OutputFormat::Jsonl] {
+            assert_eq!(format.to_string().parse::<OutputFormat>().unwrap(), format);
+        }
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+}
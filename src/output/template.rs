@@ -0,0 +1,268 @@
+//! Line templates for the `cat` command, e.g. `{start:%Y-%m-%d} {exit} {command}`.
+//!
+//! Placeholders are written as `{name}` or `{name:spec}`, literal braces as `{{` and `}}`.
+//! Supported names are `command`, `folder`, `exit`, `duration`, `machine`, `session`, `root`,
+//! `ago` and `start`/`end`, which take an optional `strftime` spec (local time).
+use std::fmt::Write;
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Local, format::StrftimeItems};
+
+use crate::event::Event;
+
+/// format used by `{start}` and `{end}` without a spec
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Command,
+    Folder,
+    Exit,
+    Duration,
+    Machine,
+    Session,
+    Root,
+    Ago,
+    Start(String),
+    End(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+/// A parsed line template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// parse `template`
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` for unknown placeholders, unbalanced braces and invalid time formats.
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => bail!("unclosed '{{' in template {template:?}"),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(Self::parse_field(&placeholder)?));
+                }
+                '}' => bail!("unmatched '}}' in template {template:?}"),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+
+    fn parse_field(placeholder: &str) -> anyhow::Result<Field> {
+        let (name, spec) = match placeholder.split_once(':') {
+            Some((name, spec)) => (name, Some(spec)),
+            None => (placeholder, None),
+        };
+        let field = match (name, spec) {
+            ("command", None) => Field::Command,
+            ("folder", None) => Field::Folder,
+            ("exit", None) => Field::Exit,
+            ("duration", None) => Field::Duration,
+            ("machine", None) => Field::Machine,
+            ("session", None) => Field::Session,
+            ("root", None) => Field::Root,
+            ("ago", None) => Field::Ago,
+            ("start", spec) => Field::Start(Self::time_format(spec)?),
+            ("end", spec) => Field::End(Self::time_format(spec)?),
+            _ => return Err(anyhow!("unknown placeholder {{{placeholder}}}")),
+        };
+        Ok(field)
+    }
+
+    fn time_format(spec: Option<&str>) -> anyhow::Result<String> {
+        let spec = spec.unwrap_or(DEFAULT_TIME_FORMAT);
+        if StrftimeItems::new(spec).parse().is_err() {
+            bail!("invalid time format {spec:?}");
+        }
+        Ok(spec.to_string())
+    }
+
+    /// render `event` with the template, `now` is used for `{ago}`
+    #[must_use]
+    pub fn render(&self, event: &Event, now: i64, timeago: &timeago::Formatter) -> String {
+        let mut line = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => line.push_str(literal),
+                Segment::Field(field) => Self::render_field(&mut line, field, event, now, timeago),
+            }
+        }
+        line
+    }
+
+    fn render_field(
+        line: &mut String,
+        field: &Field,
+        event: &Event,
+        now: i64,
+        timeago: &timeago::Formatter,
+    ) {
+        // writing into a String is infallible
+        let _ = match field {
+            Field::Command => write!(line, "{}", event.command),
+            Field::Folder => write!(line, "{}", event.folder),
+            Field::Exit => write!(line, "{}", event.exit_code),
+            Field::Duration => {
+                let millis = event.endtime.saturating_sub(event.timestamp_millis);
+                let sign = if millis < 0 { "-" } else { "" };
+                let millis = millis.unsigned_abs();
+                write!(line, "{sign}{}.{:03}s", millis / 1000, millis % 1000)
+            }
+            Field::Machine => write!(line, "{}", event.machine),
+            Field::Session => write!(line, "{}", event.session),
+            Field::Root => write!(line, "{}", event.root.as_deref().unwrap_or_default()),
+            Field::Ago => {
+                // clock skew between machines can put the end in the future
+                let ago = now.saturating_sub(event.endtime).max(0).cast_unsigned();
+                let d = std::time::Duration::from_millis(ago);
+                write!(line, "{}", timeago.convert(d))
+            }
+            Field::Start(format) => Self::write_time(line, event.timestamp_millis, format),
+            Field::End(format) => Self::write_time(line, event.endtime, format),
+        };
+    }
+
+    fn write_time(line: &mut String, millis: i64, format: &str) -> std::fmt::Result {
+        match DateTime::from_timestamp_millis(millis) {
+            Some(time) => write!(line, "{}", time.with_timezone(&Local).format(format)),
+            None => write!(line, "{millis}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> Event {
+        Event {
+            timestamp_millis: 1_000_000_000_000,
+            command: "cargo build".to_string(),
+            endtime: 1_000_000_061_250,
+            exit_code: 101,
+            folder: "/src".into(),
+            machine: "host".into(),
+            session: "abc".into(),
+            root: None,
+        }
+    }
+
+    fn render(template: &str) -> String {
+        Template::parse(template).unwrap().render(
+            &event(),
+            1_000_000_061_250,
+            &timeago::Formatter::new(),
+        )
+    }
+
+    #[test]
+    fn plain_fields() {
+        assert_eq!(
+            render("{machine}:{session} {folder} [{exit}] {command}"),
+            "host:abc /src [101] cargo build"
+        );
+    }
+
+    #[test]
+    fn root_is_empty_without_label() {
+        assert_eq!(render("[{root}]"), "[]");
+        let mut event = event();
+        event.root = Some(std::sync::Arc::from("team"));
+        let template = Template::parse("{root}").unwrap();
+        assert_eq!(
+            template.render(&event, 0, &timeago::Formatter::new()),
+            "team"
+        );
+    }
+
+    #[test]
+    fn duration_in_seconds() {
+        assert_eq!(render("{duration}"), "61.250s");
+        let mut event = event();
+        event.endtime = event.timestamp_millis - 500;
+        let template = Template::parse("{duration}").unwrap();
+        assert_eq!(
+            template.render(&event, 0, &timeago::Formatter::new()),
+            "-0.500s"
+        );
+    }
+
+    #[test]
+    fn ago_uses_now() {
+        assert_eq!(render("{ago}"), "now");
+        let template = Template::parse("{ago}").unwrap();
+        assert_eq!(
+            template.render(&event(), 0, &timeago::Formatter::new()),
+            "now"
+        );
+    }
+
+    #[test]
+    fn start_with_spec() {
+        let expected = DateTime::from_timestamp_millis(1_000_000_000_000)
+            .unwrap()
+            .with_timezone(&Local)
+            .format("%Y")
+            .to_string();
+        assert_eq!(render("{start:%Y}"), expected);
+    }
+
+    #[test]
+    fn escaped_braces() {
+        assert_eq!(render("{{{exit}}}"), "{101}");
+    }
+
+    #[test]
+    fn unknown_placeholder_fails() {
+        assert!(Template::parse("{nope}").is_err());
+        assert!(Template::parse("{command:%Y}").is_err());
+    }
+
+    #[test]
+    fn unbalanced_braces_fail() {
+        assert!(Template::parse("oops }").is_err());
+        assert!(Template::parse("{command").is_err());
+    }
+
+    #[test]
+    fn invalid_time_format_fails() {
+        assert!(Template::parse("{start:%Q}").is_err());
+    }
+}
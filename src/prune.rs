@@ -0,0 +1,107 @@
+//! Removing events from history files, e.g. the ones marked for removal in the TUI. Files are
+//! rewritten without the events (see [`rmp::rewrite_file`]), copies of an event in several files
+//! are all removed.
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    event::{Event, EventId},
+    formats::rmp,
+};
+
+/// The events that will be removed from one history file.
+pub struct Removal {
+    pub path: PathBuf,
+    pub events: Vec<Event>,
+}
+
+/// find the events with the `marked` ids in `paths`, files without any are left out
+///
+/// # Errors
+///
+/// Will return an `Err` if a file can't be read.
+#[expect(clippy::implicit_hasher, reason = "just used by the search command")]
+pub fn plan(
+    paths: impl IntoIterator<Item = PathBuf>,
+    marked: &HashSet<EventId>,
+) -> anyhow::Result<Vec<Removal>> {
+    let mut removals = Vec::new();
+    for path in paths {
+        let events: Vec<Event> = rmp::read_file(&path)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?
+            .into_iter()
+            .filter(|event| marked.contains(&event.id()))
+            .collect();
+        if !events.is_empty() {
+            removals.push(Removal { path, events });
+        }
+    }
+    removals.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(removals)
+}
+
+/// rewrite the history file at `path` without the events with the `marked` ids, returns the
+/// number of removed events
+///
+/// # Errors
+///
+/// Will return an `Err` if reading or rewriting the file fails.
+#[expect(clippy::implicit_hasher, reason = "just used by the search command")]
+pub fn remove_events(path: &Path, marked: &HashSet<EventId>) -> anyhow::Result<usize> {
+    if !path.exists() {
+        anyhow::bail!("{} doesn't exist", path.display());
+    }
+    let mut removed = 0;
+    rmp::rewrite_file(path, |events| {
+        let before = events.len();
+        let kept: Vec<Event> = events
+            .into_iter()
+            .filter(|event| !marked.contains(&event.id()))
+            .collect();
+        removed = before - kept.len();
+        Ok((removed > 0).then_some(kept))
+    })?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::testing::{commands, event, write};
+
+    #[test]
+    fn plan_lists_affected_files() {
+        let dir = TempDir::new().unwrap();
+        let paths = [dir.path().join("a.bosh"), dir.path().join("b.bosh")];
+        write(&paths[0], [event("ls", 1), event("rm -rf", 2)]);
+        write(&paths[1], [event("pwd", 3)]);
+
+        // loaded events carry their root, ids don't include it
+        let mut marked = event("rm -rf", 2);
+        marked.root = Some(Arc::from("local"));
+        let removals = plan(paths.clone(), &HashSet::from([marked.id()])).unwrap();
+        assert_eq!(removals.len(), 1);
+        assert_eq!(removals[0].path, paths[0]);
+        assert_eq!(removals[0].events.len(), 1);
+        // planning doesn't touch the files
+        assert_eq!(commands(&paths[0]), vec!["ls", "rm -rf"]);
+    }
+
+    #[test]
+    fn remove_events_rewrites_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("a.bosh");
+        write(&path, [event("ls", 1), event("typo", 2), event("typo", 3)]);
+
+        let marked = HashSet::from([event("typo", 2).id(), event("typo", 3).id()]);
+        assert_eq!(remove_events(&path, &marked).unwrap(), 2);
+        assert_eq!(commands(&path), vec!["ls"]);
+        assert_eq!(remove_events(&path, &marked).unwrap(), 0);
+    }
+}
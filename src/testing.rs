@@ -0,0 +1,71 @@
+//! Fixtures shared by the unit tests: events with predictable fields and history files of them.
+use std::{fs::OpenOptions, path::Path};
+
+use crate::{
+    event::Event,
+    formats::rmp::{self, BinaryWriter},
+};
+
+/// an event of `command` that started and ended at `endtime`
+pub fn event(command: &str, endtime: i64) -> Event {
+    Event {
+        timestamp_millis: endtime,
+        command: command.to_string(),
+        endtime,
+        exit_code: 0,
+        folder: "/".into(),
+        machine: "m".into(),
+        session: "s".into(),
+        root: None,
+    }
+}
+
+/// replace the history file at `path` with `events`, compressed if it ends in `.bosh.zst`
+pub fn write(path: &Path, events: impl IntoIterator<Item = Event>) {
+    rmp::rewrite_file(path, |_| Ok(Some(events))).unwrap();
+}
+
+/// events `cmd <endtime>` ending at `endtimes`
+pub fn events(endtimes: &[i64]) -> Vec<Event> {
+    endtimes
+        .iter()
+        .map(|&endtime| event(&format!("cmd {endtime}"), endtime))
+        .collect()
+}
+
+/// replace the history file at `path` with [`events`] ending at `endtimes`
+pub fn write_events(path: &Path, endtimes: &[i64]) {
+    write(path, events(endtimes));
+}
+
+/// append `events` to the uncompressed history file at `path`, it is created if missing
+pub fn append(path: &Path, events: &[Event]) {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap();
+    let mut writer = BinaryWriter::new(file);
+    for event in events {
+        event.clone().write(&mut writer).unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+/// the commands of the events in the history file at `path`
+pub fn commands(path: &Path) -> Vec<String> {
+    rmp::read_file(path)
+        .unwrap()
+        .into_iter()
+        .map(|event| event.command)
+        .collect()
+}
+
+/// the endtimes of the events in the history file at `path`
+pub fn endtimes(path: &Path) -> Vec<i64> {
+    rmp::read_file(path)
+        .unwrap()
+        .into_iter()
+        .map(|event| event.endtime)
+        .collect()
+}
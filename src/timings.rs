@@ -0,0 +1,156 @@
+//! Where the time until the first results goes, printed with `--timings` to find out why a search
+//! starts slowly on a particular machine. Phases are recorded from any thread with their duration
+//! and when they ended, relative to the start of the process.
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A recorded phase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phase {
+    pub name: String,
+    /// since the start of the [`Timings`] when the phase ended
+    pub at: Duration,
+    /// how long the phase took, `None` for points in time like the first render
+    pub took: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Recorder {
+    start: Instant,
+    phases: Mutex<Vec<Phase>>,
+}
+
+/// Records phases if enabled, a cheap no-op otherwise. Clones record into the same list.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    recorder: Option<Arc<Recorder>>,
+}
+
+impl Timings {
+    #[must_use]
+    pub fn new(enabled: bool) -> Self {
+        Self::since(Instant::now(), enabled)
+    }
+
+    /// like [`Timings::new`], with phases relative to `start` (e.g. when the process started)
+    #[must_use]
+    pub fn since(start: Instant, enabled: bool) -> Self {
+        Self {
+            recorder: enabled.then(|| {
+                Arc::new(Recorder {
+                    start,
+                    phases: Mutex::new(Vec::new()),
+                })
+            }),
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// run `f` and record how long it took as `name`
+    pub fn measure<T>(&self, name: &str, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.record(name, started.elapsed());
+        result
+    }
+
+    /// record that the phase `name` just ended after `took`
+    pub fn record(&self, name: impl Into<String>, took: Duration) {
+        self.push(name.into(), Some(took));
+    }
+
+    /// record that `name` happened now, unless it was recorded before
+    pub fn mark_once(&self, name: &str) {
+        if let Some(recorder) = &self.recorder
+            && let Ok(phases) = recorder.phases.lock()
+            && phases.iter().any(|phase| phase.name == name)
+        {
+            return;
+        }
+        self.push(name.to_string(), None);
+    }
+
+    fn push(&self, name: String, took: Option<Duration>) {
+        if let Some(recorder) = &self.recorder
+            && let Ok(mut phases) = recorder.phases.lock()
+        {
+            phases.push(Phase {
+                name,
+                at: recorder.start.elapsed(),
+                took,
+            });
+        }
+    }
+
+    /// the recorded phases in the order they ended
+    #[must_use]
+    pub fn phases(&self) -> Vec<Phase> {
+        let mut phases = self
+            .recorder
+            .as_ref()
+            .and_then(|recorder| recorder.phases.lock().ok().map(|phases| phases.clone()))
+            .unwrap_or_default();
+        phases.sort_by_key(|phase| phase.at);
+        phases
+    }
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:>12} {:>12}  phase", "at", "took")?;
+        for phase in self.phases() {
+            let took = phase
+                .took
+                .map(|took| format!("{took:.3?}"))
+                .unwrap_or_default();
+            writeln!(
+                f,
+                "{:>12} {took:>12}  {}",
+                format!("{:.3?}", phase.at),
+                phase.name
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_records_nothing() {
+        let timings = Timings::default();
+        assert_eq!(timings.measure("load", || 1), 1);
+        timings.mark_once("first render");
+        assert!(!timings.is_enabled());
+        assert!(timings.phases().is_empty());
+    }
+
+    #[test]
+    fn phases_are_recorded_in_order() {
+        let timings = Timings::new(true);
+        let clone = timings.clone();
+        std::thread::spawn(move || clone.record("decode a.bosh", Duration::from_millis(3)))
+            .join()
+            .unwrap();
+        timings.measure("merge", || std::thread::sleep(Duration::from_millis(2)));
+        timings.mark_once("first render");
+        timings.mark_once("first render");
+
+        let phases = timings.phases();
+        let names: Vec<&str> = phases.iter().map(|phase| phase.name.as_str()).collect();
+        assert_eq!(names, vec!["decode a.bosh", "merge", "first render"]);
+        assert!(phases[1].took.unwrap() >= Duration::from_millis(2));
+        assert_eq!(phases[2].took, None);
+        let report = timings.to_string();
+        assert!(report.contains("first render") && report.contains("3.000ms"));
+    }
+}
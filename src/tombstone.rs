@@ -0,0 +1,190 @@
+//! Deletion records in `.tomb` files next to the history files. Removing an event by rewriting a
+//! file doesn't survive copies of it on other machines (or read-only roots), a tombstone does: it
+//! is synced like any history file and hides its event in every file it is loaded from.
+//!
+//! Tombstones use the same framing as [`rmp`] files and are discovered with [`Kind::Tombstones`].
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    LoadError, OshHome,
+    event::{self, Event, EventRef},
+    formats::{Kind, rmp},
+    mmap::Mmap,
+    osh_files,
+};
+
+/// Identifies a removed event by where and when it was started and its command, the fields of its
+/// [`Event::id`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub machine: String,
+    pub session: String,
+    pub timestamp_millis: i64,
+    /// [`command_hash`] of the command, so removed commands aren't kept around in plain text
+    pub command_hash: u64,
+}
+
+impl From<EventRef<'_>> for Tombstone {
+    fn from(event: EventRef<'_>) -> Self {
+        Self {
+            machine: event.machine.into(),
+            session: event.session.into(),
+            timestamp_millis: event.timestamp_millis,
+            command_hash: command_hash(event.command),
+        }
+    }
+}
+
+impl From<&Event> for Tombstone {
+    fn from(event: &Event) -> Self {
+        Self::from(event.borrowed())
+    }
+}
+
+/// FNV-1a hash of `command`, stable across versions and machines unlike the std hashers
+#[must_use]
+pub fn command_hash(command: &str) -> u64 {
+    event::fnv1a(event::FNV_OFFSET, command.as_bytes())
+}
+
+/// the tombstone file belonging to the history file at `path`, e.g. `local.tomb` for `local.bosh`
+#[must_use]
+pub fn sidecar(path: &Path) -> PathBuf {
+    path.with_extension(Kind::Tombstones.extension())
+}
+
+/// append `tombstones` to the file at `path`, creating it if needed
+///
+/// # Errors
+///
+/// Will return an `Err` if the file can't be opened or written.
+pub fn append(path: &Path, tombstones: impl IntoIterator<Item = Tombstone>) -> anyhow::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut writer = rmp::BinaryWriter::new(file);
+    for tombstone in tombstones {
+        writer.write_record(&tombstone)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// read all tombstones in the file at `path`
+///
+/// # Errors
+///
+/// Will return an `Err` if the file can't be read or decoded.
+pub fn read_file(path: &Path) -> std::io::Result<Vec<Tombstone>> {
+    let file = std::fs::File::open(path)?;
+    rmp::load_records(&Mmap::new(&file)?)
+}
+
+impl Tombstone {
+    /// whether `self` was recorded for `event` (or a copy of it)
+    #[must_use]
+    pub fn covers(&self, event: EventRef<'_>) -> bool {
+        self.timestamp_millis == event.timestamp_millis
+            && self.machine == event.machine
+            && self.session == event.session
+            && self.command_hash == command_hash(event.command)
+    }
+}
+
+/// All tombstones of a [`OshHome`].
+#[derive(Debug, Default)]
+pub struct Tombstones {
+    /// by start time, which rarely matches, so checking an event neither allocates nor hashes
+    removed: HashMap<i64, Vec<Tombstone>>,
+}
+
+impl Tombstones {
+    /// load the tombstone files of `home`, files that fail to load are passed to `report` and
+    /// skipped
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if collecting tombstone files fails.
+    pub fn load(home: &OshHome, mut report: impl FnMut(LoadError)) -> anyhow::Result<Self> {
+        let mut tombstones = Self::default();
+        for path in osh_files(home, &Kind::Tombstones)?.into_keys() {
+            match read_file(&path) {
+                Ok(read) => tombstones.extend(read),
+                Err(e) => report(LoadError::new(&path, e)),
+            }
+        }
+        Ok(tombstones)
+    }
+
+    /// whether `event` was removed
+    #[must_use]
+    pub fn covers(&self, event: EventRef<'_>) -> bool {
+        self.removed
+            .get(&event.timestamp_millis)
+            .is_some_and(|tombstones| tombstones.iter().any(|tombstone| tombstone.covers(event)))
+    }
+}
+
+impl Extend<Tombstone> for Tombstones {
+    fn extend<T: IntoIterator<Item = Tombstone>>(&mut self, iter: T) {
+        for tombstone in iter {
+            let same_start = self.removed.entry(tombstone.timestamp_millis).or_default();
+            if !same_start.contains(&tombstone) {
+                same_start.push(tombstone);
+            }
+        }
+    }
+}
+
+impl FromIterator<Tombstone> for Tombstones {
+    fn from_iter<T: IntoIterator<Item = Tombstone>>(iter: T) -> Self {
+        let mut tombstones = Self::default();
+        tombstones.extend(iter);
+        tombstones
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::testing::event;
+
+    #[test]
+    fn command_hash_is_fnv1a() {
+        assert_eq!(command_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(command_hash("a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn append_and_read() {
+        let dir = TempDir::new().unwrap();
+        let path = sidecar(&dir.path().join("local.bosh"));
+        assert_eq!(path, dir.path().join("local.tomb"));
+
+        append(&path, [Tombstone::from(&event("ls", 1))]).unwrap();
+        append(&path, [Tombstone::from(&event("rm -rf", 2))]).unwrap();
+        let tombstones = read_file(&path).unwrap();
+        assert_eq!(tombstones.len(), 2);
+        assert_eq!(tombstones[1].command_hash, command_hash("rm -rf"));
+    }
+
+    #[test]
+    fn covers_same_start_and_command() {
+        let tombstones: Tombstones = [Tombstone::from(&event("rm -rf", 2))].into_iter().collect();
+        let mut copy = event("rm -rf", 2);
+        copy.endtime = 5;
+        copy.folder = "/tmp".into();
+        assert!(tombstones.covers(copy.borrowed()));
+        assert!(!tombstones.covers(event("rm -rf", 3).borrowed()));
+        assert!(!tombstones.covers(event("ls", 2).borrowed()));
+        let mut other_session = event("rm -rf", 2);
+        other_session.session = "t".into();
+        assert!(!tombstones.covers(other_session.borrowed()));
+    }
+}
@@ -1,6 +1,6 @@
 //! Ratatui-based TUI. Entry point is [`Tui::start`].
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashSet,
     fmt::Display,
     fs::File,
     io::Write,
@@ -28,25 +28,39 @@ use ratatui::{
 };
 
 use crate::{
-    event::Event,
+    LoadError,
+    config::Exclusions,
+    event::{Event, EventId, EventRef},
     matcher::{FuzzyEngine, FuzzyIndex, Match},
+    timings::Timings,
 };
+use store::EventStore;
 
-struct EventReader {
+pub mod store;
+
+struct EventReader<'a> {
     // TODO this is a bit ugly can we refactor this?
-    // maybe Cow is enough here
-    buffer: Arc<Mutex<Vec<Arc<Event>>>>,
+    buffer: Arc<Mutex<Vec<EventRef<'a>>>>,
 }
 
-impl EventReader {
+impl<'a> EventReader<'a> {
     fn new() -> Self {
         Self {
             buffer: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
+    fn take(&self) -> Vec<EventRef<'a>> {
+        self.buffer
+            .lock()
+            .map(|mut buffer| std::mem::take(&mut *buffer))
+            .unwrap_or_default()
+    }
+}
+
+impl EventReader<'static> {
     #[must_use]
-    fn start(self, receiver: Receiver<Arc<Event>>) -> Self {
+    fn start(self, receiver: Receiver<EventRef<'static>>) -> Self {
         let buffer = Arc::clone(&self.buffer);
         thread::spawn(move || {
             while let Ok(event) = receiver.recv() {
@@ -57,13 +71,6 @@ impl EventReader {
         });
         self
     }
-
-    fn take(&self) -> Vec<Arc<Event>> {
-        self.buffer
-            .lock()
-            .map(|mut buffer| std::mem::take(&mut *buffer))
-            .unwrap_or_default()
-    }
 }
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
@@ -72,6 +79,7 @@ pub enum EventFilter {
     SessionId,
     Folder,
     ExitCodeSuccess,
+    Root,
 }
 
 impl Display for EventFilter {
@@ -81,6 +89,41 @@ impl Display for EventFilter {
             EventFilter::SessionId => write!(f, "session id"),
             EventFilter::Folder => write!(f, "folder"),
             EventFilter::ExitCodeSuccess => write!(f, "exit code success"),
+            EventFilter::Root => write!(f, "root"),
+        }
+    }
+}
+
+/// Values the filters compare events against.
+#[derive(Debug, Clone, Default)]
+pub struct FilterContext {
+    /// folder for [`EventFilter::Folder`]
+    pub folder: String,
+    /// session for [`EventFilter::SessionId`], all sessions pass without one
+    pub session_id: Option<String>,
+    /// root label for [`EventFilter::Root`], all roots pass without one
+    pub root: Option<String>,
+    /// commands hidden regardless of the active filters
+    pub exclude: Exclusions,
+}
+
+impl EventFilter {
+    /// whether `event` passes the filter. [`EventFilter::Duplicates`] depends on the other events
+    /// and is always true here.
+    #[must_use]
+    pub fn matches(&self, event: EventRef<'_>, context: &FilterContext) -> bool {
+        match self {
+            EventFilter::Duplicates => true,
+            EventFilter::SessionId => context
+                .session_id
+                .as_ref()
+                .is_none_or(|sid| event.session == sid),
+            EventFilter::Folder => event.folder == context.folder,
+            EventFilter::ExitCodeSuccess => event.exit_code == 0,
+            EventFilter::Root => context
+                .root
+                .as_deref()
+                .is_none_or(|root| event.root.map(AsRef::as_ref) == Some(root)),
         }
     }
 }
@@ -104,90 +147,56 @@ impl FromStr for EventFilter {
             "session_id" => Ok(EventFilter::SessionId),
             "folder" => Ok(EventFilter::Folder),
             "exit_code_success" => Ok(EventFilter::ExitCodeSuccess),
+            "root" => Ok(EventFilter::Root),
             _ => Err(ParseEventFilterError(filter.to_string())),
         }
     }
 }
 
-/// View after filtering Events
-struct FilteredView<'a> {
-    events: &'a [Arc<Event>],
-    indices: Vec<usize>,
-}
-
-impl<'a> FilteredView<'a> {
-    fn build(
-        events: &'a [Arc<Event>],
-        filters: &HashSet<EventFilter>,
-        folder: &str,
-        session_id: Option<&str>,
-        dedup_map: &HashMap<String, usize>,
-    ) -> Self {
-        let indices = if filters.contains(&EventFilter::Duplicates) {
-            let mut dedup_indices: Vec<usize> = dedup_map.values().copied().collect();
-            dedup_indices.sort_unstable();
-            dedup_indices
-        } else {
-            (0..events.len()).collect()
-        };
-
-        let indices = indices
-            .into_iter()
-            .filter(|&i| {
-                #[expect(
-                    clippy::indexing_slicing,
-                    reason = "invariant by construction: i < self.events.len()"
-                )]
-                let event = &events[i];
-                filters.iter().all(|f| match f {
-                    EventFilter::Duplicates => true,
-                    EventFilter::SessionId => session_id.is_none_or(|sid| event.session == sid),
-                    EventFilter::Folder => event.folder == folder,
-                    EventFilter::ExitCodeSuccess => event.exit_code == 0,
-                })
-            })
-            .collect();
-
-        Self { events, indices }
-    }
-
-    fn entries(&self) -> impl Iterator<Item = (usize, &str)> {
-        #[expect(
-            clippy::indexing_slicing,
-            reason = "invariant by construction: i < self.events.len()"
-        )]
-        self.indices
-            .iter()
-            .map(|&i| (i, self.events[i].command.as_str()))
-    }
+/// What the user did in the TUI.
+#[derive(Default)]
+pub struct Outcome {
+    /// the event picked with enter
+    pub selected: Option<Event>,
+    /// events marked for removal with delete
+    pub marked: Vec<Event>,
 }
 
 pub struct Tui;
 
 impl Tui {
-    /// Set up the terminal and run the TUI. `receiver` is fed [`Event`]s by the caller.
-    /// Returns the selected event, if any.
+    /// Set up the terminal and run the TUI. `receiver` is fed borrowed events by the caller, files
+    /// that failed to load are reported through `errors` and shown in the status line. `roots` are
+    /// the labels [`EventFilter::Root`] cycles through.
+    /// Returns an owned copy of the selected event, if any, and of the events marked for removal.
     #[must_use]
+    #[expect(
+        clippy::too_many_arguments,
+        reason = "the options of the search command"
+    )]
     pub fn start(
-        receiver: Receiver<Arc<Event>>,
+        receiver: Receiver<EventRef<'static>>,
+        errors: Receiver<LoadError>,
         query: &str,
-        folder: &str,
-        session_id: Option<String>,
+        context: FilterContext,
         filters: HashSet<EventFilter>,
         show_score: bool,
-    ) -> Option<Event> {
+        roots: Vec<String>,
+        timings: Timings,
+    ) -> Outcome {
         let reader = EventReader::new().start(receiver);
         Tui::setup_terminal()
             .and_then(|mut terminal| {
-                let result = App::new(
+                let app = App::new(
                     reader,
+                    errors,
                     query.to_string(),
-                    folder.to_string(),
-                    session_id,
+                    context,
                     filters,
                     show_score,
-                )
-                .run(&mut terminal);
+                    roots,
+                );
+                let result = App { timings, ..app }.run(&mut terminal);
                 Tui::restore_terminal(&mut terminal)?;
                 result
             })
@@ -213,7 +222,7 @@ impl Tui {
 }
 
 /// app holds the state of the application
-struct App {
+struct App<'a> {
     /// current value of the input box
     input: String,
     /// position of cursor in the editor area.
@@ -221,28 +230,35 @@ struct App {
     /// indices into events sorted according to fuzzy score if we have a query
     indexer: Option<FuzzyIndex>,
     /// reader for collecting events from background thread
-    reader: EventReader,
+    reader: EventReader<'a>,
+    /// files that failed to load, reported by the background thread
+    error_receiver: Receiver<LoadError>,
+    load_errors: Vec<LoadError>,
     /// accumulated events pool for filtering and matching
-    events: Vec<Arc<Event>>,
+    events: EventStore<'a>,
     /// currently selected index in the history widget (0 = bottom-most)
     selected_index: usize,
     /// currently active event filter
     filters: HashSet<EventFilter>,
-    folder: String,
-    session_id: Option<String>,
+    context: FilterContext,
+    /// labels of all history roots
+    roots: Vec<String>,
     show_score: bool,
-    /// deduplicated list of entries (see [`EventFilter::Duplicates`])
-    dedup_map: HashMap<String, usize>,
+    /// ids of the events marked for removal, loaded events are unique by id
+    marked: HashSet<EventId>,
+    /// records when the first events, render and match happened (see `--timings`)
+    timings: Timings,
 }
 
-impl App {
+impl<'a> App<'a> {
     fn new(
-        reader: EventReader,
+        reader: EventReader<'a>,
+        error_receiver: Receiver<LoadError>,
         query: String,
-        folder: String,
-        session_id: Option<String>,
+        context: FilterContext,
         filters: HashSet<EventFilter>,
         show_score: bool,
+        roots: Vec<String>,
     ) -> Self {
         // TODO we should truncate (or handle) query inputs that are wider than the screen?
         let character_index = u16::try_from(query.len()).unwrap_or(u16::MAX);
@@ -251,34 +267,34 @@ impl App {
             indexer: None,
             character_index,
             reader,
-            events: Vec::new(),
+            error_receiver,
+            load_errors: Vec::new(),
+            events: EventStore::new(context.exclude.clone()),
             selected_index: 0,
             filters,
-            folder,
-            session_id,
+            context,
+            roots,
             show_score,
-            dedup_map: HashMap::new(),
+            marked: HashSet::new(),
+            timings: Timings::default(),
         }
     }
 
     fn collect_new_events(&mut self) {
-        let mut new_events = self.reader.take();
-        let base = self.events.len();
-        for (i, e) in new_events.iter().enumerate() {
-            self.dedup_map.entry(e.command.clone()).or_insert(base + i);
+        self.load_errors.extend(self.error_receiver.try_iter());
+        // the initial load arrives newest first, live updates from the watcher are newer
+        self.events.extend(self.reader.take());
+        if !self.events.is_empty() {
+            self.timings.mark_once("first events in the tui");
         }
-        self.events.append(&mut new_events);
     }
 
     fn run_matcher(&mut self) {
-        let filtered = FilteredView::build(
-            &self.events,
-            &self.filters,
-            &self.folder,
-            self.session_id.as_deref(),
-            &self.dedup_map,
-        );
-        let entries: Vec<(usize, &str)> = filtered.entries().collect();
+        let filtered = self.events.filtered(&self.filters, &self.context);
+        let entries: Vec<(usize, &str)> = filtered
+            .into_iter()
+            .filter_map(|i| Some((i, self.events.get(i)?.command)))
+            .collect();
 
         if self.input.is_empty() {
             // pass through (no score, no highlights)
@@ -293,6 +309,13 @@ impl App {
             result.sort_unstable_by_key(|(_, score, _)| std::cmp::Reverse(*score));
             self.indexer = Some(FuzzyIndex::from(result));
         }
+        if self
+            .indexer
+            .as_ref()
+            .is_some_and(|indexer| !indexer.is_empty())
+        {
+            self.timings.mark_once("first match");
+        }
         self.selected_index = 0;
     }
 
@@ -387,6 +410,28 @@ impl App {
         }
     }
 
+    /// step the root filter through all roots and switch it off after the last one
+    fn cycle_root(&mut self) {
+        let next = if self.filters.contains(&EventFilter::Root) {
+            let current = self.context.root.as_ref();
+            self.roots
+                .iter()
+                .skip_while(|root| Some(*root) != current)
+                .nth(1)
+        } else {
+            self.roots.first()
+        };
+        match next {
+            Some(root) => {
+                self.context.root = Some(root.clone());
+                self.filters.insert(EventFilter::Root);
+            }
+            None => {
+                self.filters.remove(&EventFilter::Root);
+            }
+        }
+    }
+
     fn active_filters(&self) -> String {
         // TODO find a nicer way to visualise the active filters
         self.filters
@@ -396,17 +441,78 @@ impl App {
                 EventFilter::SessionId => "S".to_string(),
                 EventFilter::Folder => "F".to_string(),
                 EventFilter::ExitCodeSuccess => "E".to_string(),
+                EventFilter::Root => match &self.context.root {
+                    Some(root) => format!("R:{root}"),
+                    None => "R".to_string(),
+                },
             })
             .collect::<Vec<_>>()
             .join(" | ")
     }
 
-    fn run(
-        mut self,
-        terminal: &mut Terminal<CrosstermBackend<File>>,
-    ) -> anyhow::Result<Option<Event>> {
+    fn is_marked(&self, event: EventRef<'_>) -> bool {
+        self.marked.contains(&event.id())
+    }
+
+    /// mark the selected event for removal, or all events with its command with `same_command`.
+    /// Unmarks them if the selected event is already marked.
+    fn toggle_mark(&mut self, same_command: bool) {
+        let Some(selected) = self
+            .indexer
+            .as_ref()
+            .and_then(|indexer| indexer.get(self.selected_index))
+            .and_then(|idx| self.events.get(idx))
+        else {
+            return;
+        };
+        let affected: Vec<EventId> = if same_command {
+            self.events
+                .iter()
+                .filter(|event| event.command == selected.command)
+                .map(|event| event.id())
+                .collect()
+        } else {
+            vec![selected.id()]
+        };
+        if self.is_marked(selected) {
+            for id in &affected {
+                self.marked.remove(id);
+            }
+        } else {
+            self.marked.extend(affected);
+        }
+    }
+
+    /// owned copies of the `selected` and the marked events, newest first
+    fn outcome(self, selected: Option<EventRef<'_>>) -> Outcome {
+        let marked = if self.marked.is_empty() {
+            Vec::new()
+        } else {
+            self.events
+                .iter()
+                .filter(|&event| self.is_marked(event))
+                .map(Event::from)
+                .collect()
+        };
+        Outcome {
+            selected: selected.map(Event::from),
+            marked,
+        }
+    }
+
+    /// summary of the files that failed to load for the status line
+    fn load_errors_status(&self) -> Option<String> {
+        let first = self.load_errors.first()?;
+        match self.load_errors.len() {
+            1 => Some(format!("failed to load {first}")),
+            n => Some(format!("failed to load {n} files, first {first}")),
+        }
+    }
+
+    fn run(mut self, terminal: &mut Terminal<CrosstermBackend<File>>) -> anyhow::Result<Outcome> {
         self.collect_new_events();
         terminal.draw(|frame| self.render(frame))?;
+        self.timings.mark_once("first render");
 
         loop {
             if event::poll(Duration::from_millis(100))? {
@@ -417,18 +523,15 @@ impl App {
                     event::Event::Key(key) => {
                         match (key.code, key.modifiers) {
                             (KeyCode::Enter, _) => {
-                                let Some(indexer) = self.indexer else {
-                                    return Ok(None);
+                                let Some(indexer) = &self.indexer else {
+                                    return Ok(self.outcome(None));
                                 };
                                 let idx = indexer.get(self.selected_index).ok_or(anyhow!(
                                     "index {:?} not in indexer",
                                     self.selected_index
                                 ))?;
-                                if let Some(event) = self.events.get(idx) {
-                                    let event = Arc::unwrap_or_clone(event.clone());
-                                    return Ok(Some(event));
-                                }
-                                return Ok(None);
+                                let selected = self.events.get(idx);
+                                return Ok(self.outcome(selected));
                             }
                             (
                                 KeyCode::Char(to_insert),
@@ -452,6 +555,10 @@ impl App {
                                 self.toggle_filter(EventFilter::ExitCodeSuccess);
                                 self.run_matcher();
                             }
+                            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                                self.cycle_root();
+                                self.run_matcher();
+                            }
                             (KeyCode::Char('x'), KeyModifiers::CONTROL) => {
                                 self.show_score = !self.show_score;
                                 self.run_matcher();
@@ -459,6 +566,8 @@ impl App {
                             (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
                                 self.delete_word();
                             }
+                            (KeyCode::Delete, KeyModifiers::SHIFT) => self.toggle_mark(true),
+                            (KeyCode::Delete, _) => self.toggle_mark(false),
                             (KeyCode::Backspace, _) => self.delete_char(),
                             (KeyCode::Left, _) => self.move_cursor_left(),
                             (KeyCode::Right, _) => self.move_cursor_right(),
@@ -470,7 +579,7 @@ impl App {
                             (KeyCode::Down, _) => self.move_selection_down(),
                             (KeyCode::Esc, _)
                             | (KeyCode::Char('c' | 'd'), KeyModifiers::CONTROL) => {
-                                return Ok(None);
+                                return Ok(self.outcome(None));
                             }
                             _ => {}
                         }
@@ -481,10 +590,14 @@ impl App {
                 }
             } else {
                 let events_before = self.events.len();
+                let errors_before = self.load_errors.len();
                 self.collect_new_events();
                 if self.events.len() != events_before {
                     self.run_matcher();
                     terminal.draw(|frame| self.render(frame))?;
+                    self.timings.mark_once("first render with events");
+                } else if self.load_errors.len() != errors_before {
+                    terminal.draw(|frame| self.render(frame))?;
                 }
             }
         }
@@ -494,7 +607,7 @@ impl App {
         &self,
         indexer: &FuzzyIndex,
         num_items: usize,
-        timeago_fn: impl Fn(&Event) -> String,
+        timeago_fn: impl Fn(EventRef<'_>) -> String,
     ) -> Vec<ListItem<'_>> {
         indexer
             .first_n(num_items)
@@ -504,7 +617,7 @@ impl App {
                 // TODO should always be Some(...): skip, report, log otherwise?
                 let event = self.events.get(idx)?;
                 let ago = timeago_fn(event);
-                let command = &event.command;
+                let command = event.command;
                 let mut spans = Vec::new();
                 spans.push(Span::raw(format!("{ago} -- ")));
                 if let Some(hl_indides) = indexer.highlight_indices(i) {
@@ -534,7 +647,7 @@ impl App {
                         spans.push(Span::raw(text));
                     }
                 } else {
-                    spans.push(Span::raw(command.as_str()));
+                    spans.push(Span::raw(command));
                 }
 
                 if self.show_score
@@ -543,12 +656,14 @@ impl App {
                     spans.push(Span::raw(format!(" ({score})")));
                 }
 
-                let item = ListItem::new(Line::from(spans));
+                let mut style = Style::default();
+                if self.is_marked(event) {
+                    style = style.fg(Color::Red).crossed_out();
+                }
                 if i == self.selected_index {
-                    Some(item.style(Style::default().bg(Color::DarkGray)))
-                } else {
-                    Some(item)
+                    style = style.bg(Color::DarkGray);
                 }
+                Some(ListItem::new(Line::from(spans)).style(style))
             })
             .collect()
     }
@@ -566,7 +681,7 @@ impl App {
 
         let now = Utc::now().timestamp_millis();
         let timeago_fmt = timeago::Formatter::new();
-        let timeago_fn = |event: &Event| {
+        let timeago_fn = |event: EventRef<'_>| {
             timeago_fmt.convert(std::time::Duration::from_millis(
                 (now - event.endtime).cast_unsigned(),
             ))
@@ -586,7 +701,20 @@ impl App {
             0
         };
         let status_text = format!("{filtered}/{}", self.events.len());
-        let status_line = Line::from(vec![Span::raw("  "), Span::raw(status_text)]);
+        let mut status_spans = vec![Span::raw("  "), Span::raw(status_text)];
+        if !self.marked.is_empty() {
+            status_spans.push(Span::styled(
+                format!("  {} marked for removal", self.marked.len()),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        if let Some(status) = self.load_errors_status() {
+            status_spans.push(Span::styled(
+                format!("  {status}"),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        let status_line = Line::from(status_spans);
         let filters = format!("[{}]  ", self.active_filters());
         let status_line_chunks = Layout::default()
             .direction(Direction::Horizontal)
@@ -618,7 +746,13 @@ impl App {
             && let Some(idx) = indexer.get(self.selected_index)
         {
             if let Some(event) = self.events.get(idx) {
-                format!("[exit code={}]: {}", event.exit_code, event.command)
+                match event.root {
+                    Some(root) if self.roots.len() > 1 => format!(
+                        "[exit code={}, root={root}]: {}",
+                        event.exit_code, event.command
+                    ),
+                    _ => format!("[exit code={}]: {}", event.exit_code, event.command),
+                }
             } else {
                 String::new()
             }
@@ -639,24 +773,33 @@ impl App {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{arena::Arena, testing::event};
 
-    fn make_app(input: &str) -> App {
+    fn make_app<'a>(input: &str) -> App<'a> {
         let character_index = u16::try_from(input.chars().count()).unwrap();
         App {
             input: input.to_string(),
             character_index,
             indexer: None,
             reader: EventReader::new(),
-            events: Vec::new(),
+            error_receiver: crossbeam_channel::never(),
+            load_errors: Vec::new(),
+            events: EventStore::default(),
             selected_index: 0,
             filters: HashSet::new(),
-            folder: String::new(),
-            session_id: None,
+            context: FilterContext::default(),
+            roots: Vec::new(),
             show_score: false,
-            dedup_map: HashMap::new(),
+            marked: HashSet::new(),
+            timings: Timings::default(),
         }
     }
 
+    /// an arena that lives as long as the events sent to [`EventReader::start`] need to
+    fn leaked_arena() -> &'static Arena {
+        Box::leak(Box::default())
+    }
+
     #[test]
     fn delete_word_basic() {
         let mut app = make_app("hello world");
@@ -819,22 +962,69 @@ mod tests {
         let mut app = make_app("");
         app.reader = EventReader::new().start(receiver);
 
-        let event = Arc::new(Event {
-            timestamp_millis: 0,
-            command: "git status".to_string(),
-            endtime: 1000,
-            exit_code: 0,
-            folder: "/".to_string(),
-            machine: "m".to_string(),
-            session: "s".to_string(),
-        });
-        sender.send(event).unwrap();
+        let arena = leaked_arena();
+        sender
+            .send(arena.keep(event("git status", 1000)).borrowed())
+            .unwrap();
         drop(sender); // closing the channel lets us wait for the thread to drain it
         std::thread::sleep(std::time::Duration::from_millis(10));
 
         app.collect_new_events();
         assert_eq!(app.events.len(), 1);
-        assert_eq!(app.events[0].command, "git status");
+        assert_eq!(app.events.get(0).unwrap().command, "git status");
+    }
+
+    #[test]
+    fn collect_new_events_keeps_newest_first() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut app = make_app("");
+        app.reader = EventReader::new().start(receiver);
+        let arena = leaked_arena();
+        let event = |command, endtime| arena.keep(event(command, endtime)).borrowed();
+
+        sender.send(event("ls", 200)).unwrap();
+        sender.send(event("pwd", 100)).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        app.collect_new_events();
+
+        // live update from another terminal
+        sender.send(event("ls", 300)).unwrap();
+        drop(sender);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        app.collect_new_events();
+
+        let endtimes: Vec<i64> = app.events.iter().map(|e| e.endtime).collect();
+        assert_eq!(endtimes, vec![300, 200, 100]);
+        let newest = app
+            .events
+            .filtered(&HashSet::from([EventFilter::Duplicates]), &app.context);
+        assert_eq!(newest, vec![0, 2]);
+    }
+
+    #[test]
+    fn collect_new_events_collects_load_errors() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut app = make_app("");
+        app.error_receiver = receiver;
+        assert_eq!(app.load_errors_status(), None);
+
+        sender
+            .send(LoadError::new(std::path::Path::new("a.bosh"), "broken"))
+            .unwrap();
+        app.collect_new_events();
+        assert_eq!(
+            app.load_errors_status().unwrap(),
+            "failed to load a.bosh: broken"
+        );
+
+        sender
+            .send(LoadError::new(std::path::Path::new("b.bosh"), "gone"))
+            .unwrap();
+        app.collect_new_events();
+        assert_eq!(
+            app.load_errors_status().unwrap(),
+            "failed to load 2 files, first a.bosh: broken"
+        );
     }
 
     #[test]
@@ -900,6 +1090,7 @@ mod tests {
             ("session_id", EventFilter::SessionId),
             ("folder", EventFilter::Folder),
             ("exit_code_success", EventFilter::ExitCodeSuccess),
+            ("root", EventFilter::Root),
         ];
         for (input, expected) in cases {
             assert_eq!(input.parse::<EventFilter>().unwrap(), expected);
@@ -910,4 +1101,99 @@ mod tests {
     fn event_filter_from_str_unknown() {
         assert!("unknown".parse::<EventFilter>().is_err());
     }
+
+    #[test]
+    fn folder_and_session_filters() {
+        let arena = Arena::new();
+        let mut app = make_app("");
+        let events = [("/a", "s1"), ("/b", "s1"), ("/a", "s2")]
+            .iter()
+            .map(|(folder, session)| {
+                let event = Event {
+                    folder: (*folder).into(),
+                    session: (*session).into(),
+                    ..event("ls", 0)
+                };
+                arena.keep(event).borrowed()
+            })
+            .collect();
+        app.events.extend(events);
+        let matched = |app: &mut App| {
+            app.run_matcher();
+            app.indexer.as_ref().map_or(0, FuzzyIndex::len)
+        };
+
+        app.filters.insert(EventFilter::Folder);
+        app.context.folder = "/a".to_string();
+        assert_eq!(matched(&mut app), 2);
+        app.filters.insert(EventFilter::SessionId);
+        app.context.session_id = Some("s2".to_string());
+        assert_eq!(matched(&mut app), 1);
+        app.context.session_id = Some("unknown".to_string());
+        assert_eq!(matched(&mut app), 0);
+        app.context.session_id = None;
+        app.context.folder = "/c".to_string();
+        assert_eq!(matched(&mut app), 0);
+    }
+
+    #[test]
+    fn toggle_mark_selected_and_same_command() {
+        let arena = Arena::new();
+        let mut app = make_app("");
+        let events = [("ls", 3), ("pwd", 2), ("ls", 1)]
+            .into_iter()
+            .map(|(command, endtime)| arena.keep(event(command, endtime)).borrowed())
+            .collect();
+        app.events.extend(events);
+        app.run_matcher();
+
+        app.toggle_mark(false);
+        assert_eq!(app.marked.len(), 1);
+        assert!(app.is_marked(app.events.get(0).unwrap()));
+        assert!(!app.is_marked(app.events.get(2).unwrap()));
+
+        app.toggle_mark(false);
+        assert!(app.marked.is_empty());
+
+        app.toggle_mark(true);
+        assert!(app.is_marked(app.events.get(0).unwrap()));
+        assert!(app.is_marked(app.events.get(2).unwrap()));
+        let outcome = app.outcome(None);
+        let commands: Vec<&str> = outcome.marked.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["ls", "ls"]);
+    }
+
+    #[test]
+    fn cycle_root_steps_through_roots() {
+        let mut app = make_app("");
+        app.roots = vec!["mine".to_string(), "team".to_string()];
+        app.cycle_root();
+        assert_eq!(app.active_filters(), "R:mine");
+        app.cycle_root();
+        assert_eq!(app.active_filters(), "R:team");
+        app.cycle_root();
+        assert!(!app.filters.contains(&EventFilter::Root));
+    }
+
+    #[test]
+    fn root_filter_matches_label() {
+        let mut event = Event {
+            timestamp_millis: 0,
+            command: "make".to_string(),
+            endtime: 0,
+            exit_code: 0,
+            folder: "/".into(),
+            machine: "m".into(),
+            session: "s".into(),
+            root: Some(Arc::from("team")),
+        };
+        let mut context = FilterContext::default();
+        assert!(EventFilter::Root.matches(event.borrowed(), &context));
+        context.root = Some("team".to_string());
+        assert!(EventFilter::Root.matches(event.borrowed(), &context));
+        context.root = Some("mine".to_string());
+        assert!(!EventFilter::Root.matches(event.borrowed(), &context));
+        event.root = None;
+        assert!(!EventFilter::Root.matches(event.borrowed(), &context));
+    }
 }
@@ -0,0 +1,494 @@
+//! Columnar store of the events shown in the TUI. Filters only look at one or two fields, so those
+//! are kept in their own arrays (with folders, sessions and roots interned) instead of being read
+//! from every [`EventRef`]. The loaded events borrow from the history files, this is the only
+//! place their strings are interned. The events passing a filter are kept as a [`Bitset`] that is
+//! computed the first time the filter is used and extended as events arrive, so toggling a filter
+//! combines bitsets instead of scanning all events.
+//!
+//! The store grows at both ends: loading adds older events at the back, live updates add newer
+//! ones at the front. Only events that fall between stored ones reorder it.
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet, VecDeque},
+};
+
+use super::{EventFilter, FilterContext};
+use crate::{
+    config::Exclusions,
+    event::EventRef,
+    intern::{Interner, Symbol},
+};
+
+/// A fixed set of positions, one bit per event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bitset {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitset {
+    /// `len` bits, all set
+    #[must_use]
+    pub fn full(len: usize) -> Self {
+        let mut bitset = Self::default();
+        for _ in 0..len {
+            bitset.push(true);
+        }
+        bitset
+    }
+
+    pub fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        if bit && let Some(word) = self.words.last_mut() {
+            *word |= 1 << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    /// clear the bit at `i`
+    pub fn unset(&mut self, i: usize) {
+        if let Some(word) = self.words.get_mut(i / 64) {
+            *word &= !(1 << (i % 64));
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, i: usize) -> bool {
+        i < self.len
+            && self
+                .words
+                .get(i / 64)
+                .is_some_and(|word| word & (1 << (i % 64)) != 0)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// keep only the bits that are also set in `other`
+    pub fn intersect(&mut self, other: &Bitset) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word &= other;
+        }
+    }
+
+    /// positions of the set bits, ascending
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
+}
+
+/// Position of an event that doesn't change when events are added at the front: negative for
+/// events added at the front (-1 for the first), counting up from 0 for the ones at the back.
+type Slot = isize;
+
+/// A [`Bitset`] in two halves that grow away from each other, see [`Slot`].
+#[derive(Debug, Clone, Default)]
+struct Bits {
+    /// bits of the events added at the front, in the order they were added
+    newer: Bitset,
+    /// bits of the events added at the back
+    older: Bitset,
+}
+
+impl Bits {
+    fn full(newer: usize, older: usize) -> Self {
+        Self {
+            newer: Bitset::full(newer),
+            older: Bitset::full(older),
+        }
+    }
+
+    fn push(&mut self, slot: Slot, bit: bool) {
+        match usize::try_from(slot) {
+            Ok(_) => self.older.push(bit),
+            Err(_) => self.newer.push(bit),
+        }
+    }
+
+    fn unset(&mut self, slot: Slot) {
+        match usize::try_from(slot) {
+            Ok(older) => self.older.unset(older),
+            Err(_) => self.newer.unset(slot.unsigned_abs() - 1),
+        }
+    }
+
+    fn intersect(&mut self, other: &Bits) {
+        self.newer.intersect(&other.newer);
+        self.older.intersect(&other.older);
+    }
+
+    /// positions of the set bits, newest first
+    fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        let newer = self.newer.len();
+        let front: Vec<usize> = self.newer.ones().map(|j| newer - 1 - j).collect();
+        front
+            .into_iter()
+            .rev()
+            .chain(self.older.ones().map(move |k| newer + k))
+    }
+}
+
+/// What a cached bitset was computed for, filters that compare against the context are cached per
+/// value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum BitsetKey {
+    Included,
+    Newest,
+    Folder(String),
+    Session(String),
+    Success,
+    Root(String),
+}
+
+/// Events sorted newest first, stored as columns.
+#[derive(Default)]
+pub struct EventStore<'a> {
+    /// the events for rendering and selection
+    events: VecDeque<EventRef<'a>>,
+    endtimes: VecDeque<i64>,
+    exit_codes: VecDeque<i16>,
+    folders: VecDeque<Symbol>,
+    sessions: VecDeque<Symbol>,
+    roots: VecDeque<Option<Symbol>>,
+    /// number of events added at the front
+    front: usize,
+    interner: Interner,
+    /// slot of the newest event per command (see [`EventFilter::Duplicates`])
+    newest: HashMap<&'a str, Slot>,
+    /// commands that are hidden regardless of the filters
+    exclude: Exclusions,
+    bitsets: HashMap<BitsetKey, Bits>,
+}
+
+impl<'a> EventStore<'a> {
+    #[must_use]
+    pub fn new(exclude: Exclusions) -> Self {
+        Self {
+            exclude,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    #[must_use]
+    pub fn get(&self, i: usize) -> Option<EventRef<'a>> {
+        self.events.get(i).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = EventRef<'a>> + '_ {
+        self.events.iter().copied()
+    }
+
+    /// add `events`. Events older than the stored ones (e.g. a newest first load) are added at
+    /// the back and newer ones (e.g. live updates) at the front, both extend the cached bitsets.
+    /// Events in between reorder the store and drop them.
+    pub fn extend(&mut self, mut events: Vec<EventRef<'a>>) {
+        events.sort_by_key(|event| Reverse(event.endtime));
+        let newer = match self.endtimes.front() {
+            Some(&newest) => events.partition_point(|event| event.endtime >= newest),
+            None => 0,
+        };
+        let in_order = match (self.endtimes.back(), events.get(newer)) {
+            (Some(&oldest), Some(event)) => event.endtime <= oldest,
+            _ => true,
+        };
+        if in_order {
+            let older = events.split_off(newer);
+            for event in events.into_iter().rev() {
+                self.push(event, true);
+            }
+            for event in older {
+                self.push(event, false);
+            }
+        } else {
+            let mut all: Vec<EventRef<'a>> = std::mem::take(&mut self.events).into();
+            all.extend(events);
+            all.sort_by_key(|event| Reverse(event.endtime));
+            self.endtimes.clear();
+            self.exit_codes.clear();
+            self.folders.clear();
+            self.sessions.clear();
+            self.roots.clear();
+            self.front = 0;
+            self.newest.clear();
+            self.bitsets.clear();
+            for event in all {
+                self.push(event, false);
+            }
+        }
+    }
+
+    /// slot of the event at `i`
+    fn slot(&self, i: usize) -> Slot {
+        #[expect(clippy::cast_possible_wrap, reason = "positions fit in memory")]
+        let slot = i as isize - self.front as isize;
+        slot
+    }
+
+    /// add `event` at the front if it is `newer` than the stored events, at the back otherwise
+    fn push(&mut self, event: EventRef<'a>, newer: bool) {
+        let folder = self.interner.intern(event.folder);
+        let session = self.interner.intern(event.session);
+        let root = event.root.map(|root| self.interner.intern(root));
+        let i = if newer {
+            self.front += 1;
+            self.endtimes.push_front(event.endtime);
+            self.exit_codes.push_front(event.exit_code);
+            self.folders.push_front(folder);
+            self.sessions.push_front(session);
+            self.roots.push_front(root);
+            self.events.push_front(event);
+            0
+        } else {
+            self.endtimes.push_back(event.endtime);
+            self.exit_codes.push_back(event.exit_code);
+            self.folders.push_back(folder);
+            self.sessions.push_back(session);
+            self.roots.push_back(root);
+            self.events.push_back(event);
+            self.len() - 1
+        };
+        let slot = self.slot(i);
+        let replaced = if newer {
+            self.newest.insert(event.command, slot)
+        } else {
+            self.newest.entry(event.command).or_insert(slot);
+            None
+        };
+
+        let keys: Vec<BitsetKey> = self.bitsets.keys().cloned().collect();
+        for key in keys {
+            let bit = self.test(&key, i);
+            if let Some(bits) = self.bitsets.get_mut(&key) {
+                bits.push(slot, bit);
+                if key == BitsetKey::Newest
+                    && let Some(replaced) = replaced
+                {
+                    bits.unset(replaced);
+                }
+            }
+        }
+    }
+
+    /// whether the event at `i` passes the filter of `key`
+    fn test(&self, key: &BitsetKey, i: usize) -> bool {
+        let symbol = |value: &str| self.interner.get(value);
+        match key {
+            BitsetKey::Included => self
+                .events
+                .get(i)
+                .is_some_and(|event| !self.exclude.excludes(event.command)),
+            BitsetKey::Newest => {
+                self.events
+                    .get(i)
+                    .and_then(|event| self.newest.get(event.command))
+                    == Some(&self.slot(i))
+            }
+            BitsetKey::Folder(folder) => {
+                symbol(folder).is_some_and(|folder| self.folders.get(i) == Some(&folder))
+            }
+            BitsetKey::Session(session) => {
+                symbol(session).is_some_and(|session| self.sessions.get(i) == Some(&session))
+            }
+            BitsetKey::Success => self.exit_codes.get(i) == Some(&0),
+            BitsetKey::Root(root) => {
+                symbol(root).is_some_and(|root| self.roots.get(i) == Some(&Some(root)))
+            }
+        }
+    }
+
+    /// the cached bitset of `key`, computed on first use
+    fn bitset(&mut self, key: &BitsetKey) -> &Bits {
+        if !self.bitsets.contains_key(key) {
+            let mut bits = Bits::default();
+            // in the order the events were added to each half
+            for i in (0..self.front).rev().chain(self.front..self.len()) {
+                bits.push(self.slot(i), self.test(key, i));
+            }
+            self.bitsets.insert(key.clone(), bits);
+        }
+        #[expect(clippy::indexing_slicing, reason = "inserted above")]
+        &self.bitsets[key]
+    }
+
+    /// positions of the events that aren't excluded and pass all `filters`, newest first
+    pub fn filtered(
+        &mut self,
+        filters: &HashSet<EventFilter>,
+        context: &FilterContext,
+    ) -> Vec<usize> {
+        let mut keys = vec![BitsetKey::Included];
+        for filter in filters {
+            keys.extend(match filter {
+                EventFilter::Duplicates => Some(BitsetKey::Newest),
+                EventFilter::SessionId => context.session_id.clone().map(BitsetKey::Session),
+                EventFilter::Folder => Some(BitsetKey::Folder(context.folder.clone())),
+                EventFilter::ExitCodeSuccess => Some(BitsetKey::Success),
+                EventFilter::Root => context.root.clone().map(BitsetKey::Root),
+            });
+        }
+        let mut passing = Bits::full(self.front, self.len() - self.front);
+        for key in &keys {
+            passing.intersect(self.bitset(key));
+        }
+        passing.ones().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{arena::Arena, config::CommandPattern, event::Event, testing};
+
+    fn event<'a>(
+        arena: &'a Arena,
+        command: &str,
+        endtime: i64,
+        folder: &str,
+        exit_code: i16,
+    ) -> EventRef<'a> {
+        let event = Event {
+            exit_code,
+            folder: folder.into(),
+            ..testing::event(command, endtime)
+        };
+        arena.keep(event).borrowed()
+    }
+
+    /// the same filtering done by scanning the events
+    fn scanned(
+        store: &EventStore,
+        filters: &HashSet<EventFilter>,
+        context: &FilterContext,
+    ) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        let newest: Vec<bool> = store
+            .iter()
+            .map(|event| seen.insert(event.command))
+            .collect();
+        store
+            .iter()
+            .enumerate()
+            .filter(|&(i, event)| {
+                !context.exclude.excludes(event.command)
+                    && filters.iter().all(|filter| match filter {
+                        EventFilter::Duplicates => newest.get(i) == Some(&true),
+                        _ => filter.matches(event, context),
+                    })
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn bitset_ones() {
+        let mut bitset = Bitset::default();
+        for i in 0..130 {
+            bitset.push(i % 3 == 0 || i == 128);
+        }
+        assert_eq!(bitset.len(), 130);
+        assert!(bitset.get(63) && !bitset.get(64) && bitset.get(128) && !bitset.get(130));
+        let mut other = Bitset::full(130);
+        assert_eq!(other.ones().count(), 130);
+        other.intersect(&bitset);
+        assert_eq!(other, bitset);
+        assert_eq!(
+            bitset.ones().filter(|i| i % 3 != 0).collect::<Vec<_>>(),
+            vec![128]
+        );
+    }
+
+    #[test]
+    fn filters_match_a_scan() {
+        let arena = Arena::new();
+        let event = |command, endtime, folder, exit_code| {
+            event(&arena, command, endtime, folder, exit_code)
+        };
+        let exclude = Exclusions::new(vec!["secret*".parse::<CommandPattern>().unwrap()]);
+        let mut store = EventStore::new(exclude.clone());
+        let context = FilterContext {
+            folder: "/a".to_string(),
+            exclude,
+            ..FilterContext::default()
+        };
+        store.extend(vec![
+            event("ls", 6, "/a", 0),
+            event("make", 5, "/b", 1),
+            event("ls", 4, "/b", 0),
+            event("secret stuff", 3, "/a", 0),
+        ]);
+
+        let all = [
+            EventFilter::Duplicates,
+            EventFilter::Folder,
+            EventFilter::ExitCodeSuccess,
+            EventFilter::SessionId,
+            EventFilter::Root,
+        ];
+        let check = |store: &mut EventStore| {
+            for mask in 0..1 << all.len() {
+                let filters: HashSet<EventFilter> = all
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| mask & (1 << i) != 0)
+                    .map(|(_, filter)| filter.clone())
+                    .collect();
+                assert_eq!(
+                    store.filtered(&filters, &context),
+                    scanned(store, &filters, &context)
+                );
+            }
+        };
+        check(&mut store);
+        assert_eq!(
+            store.filtered(&HashSet::from([EventFilter::Folder]), &context),
+            vec![0]
+        );
+
+        // appended to the cached bitsets
+        store.extend(vec![event("make", 2, "/a", 0), event("pwd", 1, "/a", 0)]);
+        check(&mut store);
+        // live updates are added at the front and extend the cached bitsets
+        store.extend(vec![event("pwd", 7, "/c", 0), event("ls", 8, "/a", 1)]);
+        assert_eq!(store.get(0).map(|event| event.endtime), Some(8));
+        assert!(!store.bitsets.is_empty());
+        check(&mut store);
+        store.extend(vec![event("cd", 9, "/a", 0), event("cd", 0, "/a", 0)]);
+        assert_eq!(store.front, 3);
+        check(&mut store);
+        // events between stored ones reorder
+        store.extend(vec![event("make", 4, "/a", 0)]);
+        assert!(store.bitsets.is_empty() && store.front == 0);
+        let endtimes: Vec<i64> = store.iter().map(|event| event.endtime).collect();
+        assert_eq!(endtimes, vec![9, 8, 7, 6, 5, 4, 4, 3, 2, 1, 0]);
+        check(&mut store);
+    }
+}
@@ -0,0 +1,693 @@
+//! Follow history files while the TUI is open. The osh roots are watched with inotify and events
+//! appended to `.bosh` files (e.g. by other terminals) are read from the last known offset. Files
+//! replaced by a rename (rewrites, sync tools) are read again from the start, only the events that
+//! weren't in the replaced file are reported. Like loading, the watcher skips the archive folder,
+//! removed events (see [`crate::tombstone`]) and copies of reported events (see [`Event::id`]).
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::{ffi::OsStrExt, fs::MetadataExt},
+    },
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use crossbeam_channel::Sender;
+
+use crate::{
+    LoadError, OshHome,
+    arena::Arena,
+    conflict_original,
+    event::{Event, EventId, EventRef},
+    formats::{Kind, rmp},
+    oshignore::IgnoreRules,
+    tombstone::Tombstones,
+};
+
+const WATCH_MASK: u32 =
+    libc::IN_MODIFY | libc::IN_CLOSE_WRITE | libc::IN_CREATE | libc::IN_MOVED_TO;
+
+/// size of the fixed part of `struct inotify_event`, the name follows
+const EVENT_HEADER_SIZE: usize = std::mem::size_of::<libc::inotify_event>();
+
+pub struct HistoryWatcher {
+    inotify: File,
+    /// watched directories by watch descriptor
+    dirs: HashMap<i32, PathBuf>,
+    /// bytes already read per history file
+    offsets: HashMap<PathBuf, u64>,
+    /// the file that was read per path, kept open to notice (and read) replaced files
+    handles: HashMap<PathBuf, File>,
+    /// files to read on the next poll regardless of notifications
+    pending: HashSet<PathBuf>,
+    /// files that failed to load since the last [`Self::take_errors`]
+    errors: Vec<LoadError>,
+    /// roots in order of precedence
+    roots: Vec<WatchedRoot>,
+    /// skipped archive folder of the home, see [`OshHome::archive`]
+    archive: Option<PathBuf>,
+    tombstones: Tombstones,
+    /// ids of the events reported by loading and polling
+    seen: HashSet<EventId>,
+}
+
+struct WatchedRoot {
+    /// canonicalized root folder
+    path: PathBuf,
+    label: Arc<str>,
+    rules: IgnoreRules,
+}
+
+impl HistoryWatcher {
+    /// watch the roots of `home` and all folders below them, as well as the folders of the files
+    /// in `offsets` which can live outside of the roots via symlinks. `offsets` holds the number
+    /// of bytes that were already loaded per file; anything after it is reported by the first
+    /// [`Self::poll`]. `seen` are the ids of the loaded events, copies of them are not reported
+    /// again. Roots that don't exist are skipped, new files excluded by the `.oshignore` of their
+    /// root, in the skipped archive folder or conflict copies (see [`conflict_original`]) are not
+    /// read.
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if inotify can't be set up, a folder can't be watched, an
+    /// `.oshignore` is invalid or the tombstones can't be discovered.
+    pub fn new(
+        home: &OshHome,
+        offsets: HashMap<PathBuf, u64>,
+        seen: HashSet<EventId>,
+    ) -> std::io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let inotify = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+        let pending = offsets.keys().cloned().collect();
+        let handles = offsets
+            .keys()
+            .filter_map(|path| Some((path.clone(), File::open(path).ok()?)))
+            .collect();
+        let mut errors = Vec::new();
+        let tombstones =
+            Tombstones::load(home, |error| errors.push(error)).map_err(std::io::Error::other)?;
+        let mut watcher = Self {
+            inotify,
+            dirs: HashMap::new(),
+            offsets,
+            handles,
+            pending,
+            errors,
+            roots: Vec::new(),
+            archive: home.archive.as_deref().map(canonical),
+            tombstones,
+            seen,
+        };
+
+        for root in &home.roots {
+            match watcher.watch_recursive(&root.path) {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+            watcher.roots.push(WatchedRoot {
+                path: root.path.canonicalize()?,
+                label: Arc::from(root.label.as_str()),
+                rules: IgnoreRules::load(&root.path).map_err(std::io::Error::other)?,
+            });
+        }
+        let parents: HashSet<PathBuf> = watcher
+            .offsets
+            .keys()
+            .filter_map(|path| path.parent())
+            .map(Path::to_path_buf)
+            .collect();
+        for parent in parents {
+            watcher.watch(&parent)?;
+        }
+
+        Ok(watcher)
+    }
+
+    /// watch `dir`, returns `false` if it was watched already
+    fn watch(&mut self, dir: &Path) -> std::io::Result<bool> {
+        let dir = dir.canonicalize()?;
+        if self.dirs.values().any(|watched| *watched == dir) {
+            return Ok(false);
+        }
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        let wd =
+            unsafe { libc::inotify_add_watch(self.inotify.as_raw_fd(), path.as_ptr(), WATCH_MASK) };
+        if wd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        self.dirs.insert(wd, dir);
+        Ok(true)
+    }
+
+    /// watch `dir` and its sub folders, returns all history files found on the way. Folders that
+    /// are watched already aren't entered again, so symlink loops end.
+    fn watch_recursive(&mut self, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        if !self.watch(dir)? {
+            return Ok(files);
+        }
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                files.extend(self.watch_recursive(&path)?);
+            } else if is_history_file(&path) {
+                files.push(path);
+            }
+        }
+        Ok(files)
+    }
+
+    /// read all complete events appended to `path` since the last call
+    fn tail(&mut self, path: &Path) -> std::io::Result<Vec<Event>> {
+        let offset = self.offsets.get(path).copied().unwrap_or(0);
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let len = metadata.len();
+        let replaced = self.handles.get(path).and_then(|old| old.metadata().ok());
+        if let Some(old) = replaced
+            && (old.ino(), old.dev()) != (metadata.ino(), metadata.dev())
+        {
+            return self.reread(path, file, offset);
+        }
+        if !self.handles.contains_key(path) {
+            self.handles.insert(path.to_path_buf(), file.try_clone()?);
+        }
+        if len < offset {
+            // truncated in place, we can't tell what is new and continue from the end
+            self.offsets.insert(path.to_path_buf(), len);
+            return Ok(vec![]);
+        }
+
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = Vec::new();
+        file.take(len - offset).read_to_end(&mut data)?;
+        let (mut events, consumed) = rmp::load_complete_events(&data)?;
+        self.offsets
+            .insert(path.to_path_buf(), offset + consumed as u64);
+        let label = self.label(path);
+        for event in &mut events {
+            event.root.clone_from(&label);
+        }
+        Ok(events)
+    }
+
+    /// read the file that replaced `path` from the start, only events that aren't in the first
+    /// `offset` bytes (which were reported) of the replaced file are returned
+    fn reread(&mut self, path: &Path, file: File, offset: u64) -> std::io::Result<Vec<Event>> {
+        let mut reported = Vec::new();
+        if let Some(mut old) = self.handles.remove(path) {
+            old.seek(SeekFrom::Start(0))?;
+            old.take(offset).read_to_end(&mut reported)?;
+        }
+        let (reported, _) = rmp::load_complete_events(&reported)?;
+        let reported: HashSet<_> = reported.iter().map(Event::id).collect();
+
+        let mut data = Vec::new();
+        (&file).read_to_end(&mut data)?;
+        self.handles.insert(path.to_path_buf(), file);
+        let (events, consumed) = rmp::load_complete_events(&data)?;
+        self.offsets.insert(path.to_path_buf(), consumed as u64);
+        let label = self.label(path);
+        Ok(events
+            .into_iter()
+            .filter(|event| !reported.contains(&event.id()))
+            .map(|mut event| {
+                event.root.clone_from(&label);
+                event
+            })
+            .collect())
+    }
+
+    /// first root containing `path`, files outside of all roots belong to the first
+    fn root(&self, path: &Path) -> Option<&WatchedRoot> {
+        self.roots
+            .iter()
+            .find(|root| path.starts_with(&root.path))
+            .or(self.roots.first())
+    }
+
+    fn label(&self, path: &Path) -> Option<Arc<str>> {
+        self.root(path).map(|root| Arc::clone(&root.label))
+    }
+
+    /// whether `path` is a loaded or new history file that should be followed
+    fn is_followed(&self, path: &Path) -> bool {
+        self.offsets.contains_key(path)
+            || is_history_file(path)
+                && !self
+                    .archive
+                    .as_ref()
+                    .is_some_and(|archive| path.starts_with(archive))
+                && conflict_original(path).is_none()
+                && self
+                    .root(path)
+                    .is_none_or(|root| root.rules.ignored_by(path).is_none())
+    }
+
+    /// drain the inotify queue and return the history files that changed
+    fn changed_files(&mut self) -> std::io::Result<HashSet<PathBuf>> {
+        let mut changed = HashSet::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let n = match self.inotify.read(&mut buffer) {
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            };
+            let mut records = buffer.get(..n).unwrap_or_default();
+            while let Some((header, rest)) = records.split_at_checked(EVENT_HEADER_SIZE) {
+                let field = |at: usize| {
+                    header
+                        .get(at..at + 4)
+                        .and_then(|b| b.try_into().ok())
+                        .map_or(0, u32::from_ne_bytes)
+                };
+                let wd = field(0).cast_signed();
+                let mask = field(4);
+                let name_len = field(12) as usize;
+                let Some((name, rest)) = rest.split_at_checked(name_len) else {
+                    break;
+                };
+                records = rest;
+
+                if mask & libc::IN_Q_OVERFLOW != 0 {
+                    // we lost notifications, check everything we know
+                    changed.extend(self.offsets.keys().cloned());
+                    continue;
+                }
+                let Some(dir) = self.dirs.get(&wd) else {
+                    continue;
+                };
+                let name = name.split(|c| *c == 0).next().unwrap_or_default();
+                let path = dir.join(std::ffi::OsStr::from_bytes(name));
+                if mask & libc::IN_ISDIR != 0 {
+                    let found = self.watch_recursive(&path)?;
+                    changed.extend(found.into_iter().filter(|file| self.is_followed(file)));
+                } else if is_history_file(&path) {
+                    let path = path.canonicalize().unwrap_or(path);
+                    if self.is_followed(&path) {
+                        changed.insert(path);
+                    }
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// wait up to `timeout` for changes and return all events appended since the last call,
+    /// newest first. Removed events and copies of reported ones are skipped. Files that fail to
+    /// load are skipped, see [`Self::take_errors`].
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if reading notifications fails.
+    pub fn poll(&mut self, timeout: Duration) -> std::io::Result<Vec<Event>> {
+        let mut changed = std::mem::take(&mut self.pending);
+
+        let timeout = if changed.is_empty() {
+            libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX)
+        } else {
+            0
+        };
+        let mut pollfd = libc::pollfd {
+            fd: self.inotify.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&raw mut pollfd, 1, timeout) };
+        if ready < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        } else if ready > 0 {
+            changed.extend(self.changed_files()?);
+        }
+
+        let mut events = Vec::new();
+        for path in changed {
+            match self.tail(&path) {
+                Ok(new_events) => events.extend(new_events),
+                // removed again before we got to read it
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    // don't report the same broken data again on the next append
+                    if let Ok(metadata) = std::fs::metadata(&path) {
+                        self.offsets.insert(path.clone(), metadata.len());
+                    }
+                    self.errors.push(LoadError::new(&path, e));
+                }
+            }
+        }
+        events.sort_unstable_by(|a, b| b.cmp(a));
+        events.retain(|event| {
+            !self.tombstones.covers(event.borrowed()) && self.seen.insert(event.id())
+        });
+        Ok(events)
+    }
+
+    /// files that failed to load during the previous polls
+    pub fn take_errors(&mut self) -> Vec<LoadError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// forward appended events, kept in `arena`, to `sender` and failing files to `errors` until
+    /// the receiving side is gone
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if polling fails (see [`Self::poll`]).
+    pub fn run<'a>(
+        mut self,
+        arena: &'a Arena,
+        sender: &Sender<EventRef<'a>>,
+        errors: &Sender<LoadError>,
+    ) -> std::io::Result<()> {
+        loop {
+            for event in self.poll(Duration::from_millis(500))? {
+                if sender.send(arena.keep(event).borrowed()).is_err() {
+                    return Ok(());
+                }
+            }
+            for error in self.take_errors() {
+                if errors.send(error).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// `path` canonicalized, or below its canonicalized parent if it doesn't exist yet
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .ok()
+        .or_else(|| {
+            let parent = path.parent()?.canonicalize().ok()?;
+            Some(parent.join(path.file_name()?))
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+fn is_history_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| *extension == *Kind::Rmp.extension())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write};
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        Root,
+        formats::rmp::BinaryWriter,
+        testing::{append, event},
+        tombstone,
+    };
+
+    fn commands(events: &[Event]) -> Vec<&str> {
+        events.iter().map(|e| e.command.as_str()).collect()
+    }
+
+    fn home(paths: &[&Path]) -> OshHome {
+        OshHome {
+            roots: paths
+                .iter()
+                .enumerate()
+                .map(|(i, path)| Root {
+                    label: format!("root{i}"),
+                    path: path.to_path_buf(),
+                })
+                .collect(),
+            file: paths[0].join("local.bosh"),
+            archive: None,
+        }
+    }
+
+    /// a watcher for `root` that already loaded `files` completely
+    fn watcher(root: &Path, files: &[&Path]) -> HistoryWatcher {
+        let offsets = files
+            .iter()
+            .map(|path| {
+                (
+                    path.canonicalize().unwrap(),
+                    std::fs::metadata(path).unwrap().len(),
+                )
+            })
+            .collect();
+        HistoryWatcher::new(&home(&[root]), offsets, HashSet::new()).unwrap()
+    }
+
+    #[test]
+    fn appended_events_are_reported() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("local.bosh");
+        append(&file, &[event("old", 1)]);
+        let mut watcher = watcher(dir.path(), &[&file]);
+        assert!(watcher.poll(Duration::ZERO).unwrap().is_empty());
+
+        append(&file, &[event("new", 2), event("newer", 3)]);
+        let events = watcher.poll(Duration::from_secs(1)).unwrap();
+        assert_eq!(commands(&events), vec!["newer", "new"]);
+        assert!(watcher.poll(Duration::ZERO).unwrap().is_empty());
+    }
+
+    #[test]
+    fn appends_before_start_are_caught_up() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("local.bosh");
+        append(&file, &[event("old", 1)]);
+        let offsets = HashMap::from([(
+            file.canonicalize().unwrap(),
+            std::fs::metadata(&file).unwrap().len(),
+        )]);
+        append(&file, &[event("missed", 2)]);
+
+        let mut watcher =
+            HistoryWatcher::new(&home(&[dir.path()]), offsets, HashSet::new()).unwrap();
+        let events = watcher.poll(Duration::ZERO).unwrap();
+        assert_eq!(commands(&events), vec!["missed"]);
+    }
+
+    #[test]
+    fn partial_records_wait_for_completion() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("local.bosh");
+        append(&file, &[]);
+        let mut watcher = watcher(dir.path(), &[&file]);
+
+        let mut record = Vec::new();
+        event("slow", 1)
+            .write(&mut BinaryWriter::new(&mut record))
+            .unwrap();
+        let (head, tail) = record.split_at(record.len() / 2);
+        let mut handle = OpenOptions::new().append(true).open(&file).unwrap();
+
+        handle.write_all(head).unwrap();
+        assert!(watcher.poll(Duration::from_secs(1)).unwrap().is_empty());
+        handle.write_all(tail).unwrap();
+        let events = watcher.poll(Duration::from_secs(1)).unwrap();
+        assert_eq!(commands(&events), vec!["slow"]);
+    }
+
+    #[test]
+    fn new_files_and_folders_are_picked_up() {
+        let dir = TempDir::new().unwrap();
+        let mut watcher = watcher(dir.path(), &[]);
+
+        append(&dir.path().join("other.bosh"), &[event("other", 1)]);
+        let events = watcher.poll(Duration::from_secs(1)).unwrap();
+        assert_eq!(commands(&events), vec!["other"]);
+
+        std::fs::create_dir(dir.path().join("active")).unwrap();
+        assert!(watcher.poll(Duration::from_secs(1)).unwrap().is_empty());
+        append(&dir.path().join("active/host.bosh"), &[event("host", 2)]);
+        let events = watcher.poll(Duration::from_secs(1)).unwrap();
+        assert_eq!(commands(&events), vec!["host"]);
+    }
+
+    #[test]
+    fn broken_appends_are_reported_once() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("local.bosh");
+        append(&file, &[]);
+        let mut watcher = watcher(dir.path(), &[&file]);
+
+        let mut handle = OpenOptions::new().append(true).open(&file).unwrap();
+        handle.write_all(&4u64.to_le_bytes()).unwrap();
+        handle.write_all(b"oops").unwrap();
+        assert!(watcher.poll(Duration::from_secs(1)).unwrap().is_empty());
+        let errors = watcher.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, file.canonicalize().unwrap());
+
+        append(&file, &[event("fine", 1)]);
+        let events = watcher.poll(Duration::from_secs(1)).unwrap();
+        assert_eq!(commands(&events), vec!["fine"]);
+        assert!(watcher.take_errors().is_empty());
+    }
+
+    #[test]
+    fn events_are_labeled_by_root() {
+        let mine = TempDir::new().unwrap();
+        let team = TempDir::new().unwrap();
+        let missing = mine.path().join("missing");
+        let mut watcher = HistoryWatcher::new(
+            &home(&[mine.path(), &missing, team.path()]),
+            HashMap::new(),
+            HashSet::new(),
+        )
+        .unwrap();
+
+        append(&team.path().join("ci.bosh"), &[event("team", 1)]);
+        append(&mine.path().join("local.bosh"), &[event("mine", 2)]);
+        let mut events = watcher.poll(Duration::from_secs(1)).unwrap();
+        if events.len() < 2 {
+            events.extend(watcher.poll(Duration::from_secs(1)).unwrap());
+            events.sort_by(|a, b| b.cmp(a));
+        }
+        let labels: Vec<(&str, Option<&str>)> = events
+            .iter()
+            .map(|e| (e.command.as_str(), e.root.as_deref()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![("mine", Some("root0")), ("team", Some("root2"))]
+        );
+    }
+
+    #[test]
+    fn oshignore_is_respected() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join(".oshignore"), "archive/\n").unwrap();
+        let mut watcher = watcher(dir.path(), &[]);
+
+        std::fs::create_dir(dir.path().join("archive")).unwrap();
+        append(&dir.path().join("archive/old.bosh"), &[event("old", 1)]);
+        append(&dir.path().join("local.bosh"), &[event("local", 2)]);
+        let mut events = watcher.poll(Duration::from_secs(1)).unwrap();
+        events.extend(watcher.poll(Duration::from_millis(100)).unwrap());
+        assert_eq!(commands(&events), vec!["local"]);
+    }
+
+    #[test]
+    fn archive_and_conflict_copies_are_ignored() {
+        let dir = TempDir::new().unwrap();
+        let archive = dir.path().join("archive");
+        let mut home = home(&[dir.path()]);
+        home.archive = Some(archive.clone());
+        let mut watcher = HistoryWatcher::new(&home, HashMap::new(), HashSet::new()).unwrap();
+
+        std::fs::create_dir(&archive).unwrap();
+        append(&archive.join("2024.bosh"), &[event("archived", 1)]);
+        append(
+            &dir.path()
+                .join("local.sync-conflict-20240101-000000-ABCDEF.bosh"),
+            &[event("copy", 2)],
+        );
+        append(&dir.path().join("local.bosh"), &[event("local", 3)]);
+        let mut events = watcher.poll(Duration::from_secs(1)).unwrap();
+        events.extend(watcher.poll(Duration::from_millis(100)).unwrap());
+        assert_eq!(commands(&events), vec!["local"]);
+    }
+
+    #[test]
+    fn seen_and_removed_events_are_skipped() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("local.bosh");
+        tombstone::append(
+            &tombstone::sidecar(&file),
+            [tombstone::Tombstone::from(&event("removed", 1))],
+        )
+        .unwrap();
+        let seen = HashSet::from([event("loaded", 2).id()]);
+        let mut watcher = HistoryWatcher::new(&home(&[dir.path()]), HashMap::new(), seen).unwrap();
+
+        append(
+            &file,
+            &[
+                event("removed", 1),
+                event("loaded", 2),
+                event("new", 3),
+                event("new", 3),
+            ],
+        );
+        let events = watcher.poll(Duration::from_secs(1)).unwrap();
+        assert_eq!(commands(&events), vec!["new"]);
+    }
+
+    #[test]
+    fn other_files_are_ignored() {
+        let dir = TempDir::new().unwrap();
+        let mut watcher = watcher(dir.path(), &[]);
+        std::fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+        assert!(watcher.poll(Duration::from_millis(100)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn replaced_files_are_read_again() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("local.bosh");
+        append(&file, &[event("a", 1), event("b", 2)]);
+        let mut watcher = watcher(dir.path(), &[&file]);
+        assert!(watcher.poll(Duration::ZERO).unwrap().is_empty());
+
+        // longer than before, with the old offset in the middle of a record
+        let replacement = dir.path().join("replacement.tmp");
+        let long = "x".repeat(100);
+        append(
+            &replacement,
+            &[event(&long, 0), event("a", 1), event("b", 2), event("c", 3)],
+        );
+        std::fs::rename(&replacement, &file).unwrap();
+        let mut events = watcher.poll(Duration::from_secs(1)).unwrap();
+        events.extend(watcher.poll(Duration::from_millis(100)).unwrap());
+        assert_eq!(commands(&events), vec!["c", long.as_str()]);
+        assert!(watcher.take_errors().is_empty());
+
+        append(&file, &[event("d", 4)]);
+        let events = watcher.poll(Duration::from_secs(1)).unwrap();
+        assert_eq!(commands(&events), vec!["d"]);
+    }
+
+    #[test]
+    fn symlink_loops_are_watched_once() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("sub/loop")).unwrap();
+        let mut watcher = watcher(dir.path(), &[]);
+        assert_eq!(watcher.dirs.len(), 2);
+
+        append(&dir.path().join("sub/host.bosh"), &[event("host", 1)]);
+        let events = watcher.poll(Duration::from_secs(1)).unwrap();
+        assert_eq!(commands(&events), vec!["host"]);
+    }
+
+    #[test]
+    fn run_forwards_to_channel() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("local.bosh");
+        append(&file, &[]);
+        let watcher = watcher(dir.path(), &[&file]);
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let (errors, _) = crossbeam_channel::unbounded();
+        let arena: &'static Arena = Box::leak(Box::default());
+        std::thread::spawn(move || watcher.run(arena, &sender, &errors));
+
+        append(&file, &[event("live", 1)]);
+        let event = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.command, "live");
+    }
+}
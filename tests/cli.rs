@@ -0,0 +1,182 @@
+use std::{path::Path, process::Command};
+
+use tempfile::TempDir;
+
+fn osh(home: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_osh-oxy"));
+    command.env("OSH_HOME", home);
+    command
+}
+
+/// milliseconds of 2023-11-14, appended events are relative to it
+const NOW: i64 = 1_700_000_000_000;
+
+fn append(command: &mut Command, cmd: &str, endtime: i64) {
+    let endtime = NOW + endtime;
+    let status = command
+        .args(["append-event", "--command", cmd, "--folder", "/tmp"])
+        .args(["--starttime", &(endtime - 10).to_string()])
+        .args(["--endtime", &endtime.to_string()])
+        .args(["--exit-code", "0", "--machine", "m", "--session", "s"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn cat(command: &mut Command) -> Vec<String> {
+    let output = command
+        .args(["cat", "--format", "{command}"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn append_and_cat_use_osh_home() {
+    let home = TempDir::new().unwrap();
+    append(&mut osh(home.path()), "ls", 1_000);
+    append(&mut osh(home.path()), "pwd", 2_000);
+
+    assert!(home.path().join("local.bosh").exists());
+    assert_eq!(cat(&mut osh(home.path())), vec!["pwd", "ls"]);
+}
+
+#[test]
+fn root_and_file_override_osh_home() {
+    let home = TempDir::new().unwrap();
+    let work = TempDir::new().unwrap();
+    let file = work.path().join("active/work.bosh");
+    append(
+        osh(home.path())
+            .arg("--root")
+            .arg(work.path())
+            .arg("--file")
+            .arg(&file),
+        "make",
+        1_000,
+    );
+
+    assert!(file.exists());
+    assert!(!home.path().join("local.bosh").exists());
+    assert!(cat(&mut osh(home.path())).is_empty());
+    assert_eq!(
+        cat(osh(home.path()).arg("--root").arg(work.path())),
+        vec!["make"]
+    );
+}
+
+#[test]
+fn multiple_roots_can_be_filtered_by_label() {
+    let mine = TempDir::new().unwrap();
+    let team = TempDir::new().unwrap();
+    append(&mut osh(mine.path()), "mine", 1_000);
+    append(&mut osh(team.path()), "team", 2_000);
+
+    let roots = format!("{}:ci={}", mine.path().display(), team.path().display());
+    let both = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_osh-oxy"));
+        command.env("OSH_HOME", &roots);
+        command
+    };
+    assert_eq!(cat(&mut both()), vec!["team", "mine"]);
+    let output = both()
+        .args(["cat", "--format", "{root} {command}"])
+        .args(["--filter", "root", "--root-label", "ci"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ci team\n");
+}
+
+#[test]
+fn excluded_commands_are_not_recorded_or_shown() {
+    let home = TempDir::new().unwrap();
+    append(&mut osh(home.path()), "cd /tmp", 1_000);
+    std::fs::write(
+        home.path().join("config.toml"),
+        "exclude = [\"ls\", \"cd *\"]\n",
+    )
+    .unwrap();
+    append(&mut osh(home.path()), "ls", 2_000);
+    append(&mut osh(home.path()), "make", 3_000);
+
+    assert_eq!(cat(&mut osh(home.path())), vec!["make"]);
+    std::fs::remove_file(home.path().join("config.toml")).unwrap();
+    assert_eq!(cat(&mut osh(home.path())), vec!["make", "cd /tmp"]);
+}
+
+#[test]
+fn secrets_are_redacted_on_append() {
+    let home = TempDir::new().unwrap();
+    append(&mut osh(home.path()), "export GITHUB_TOKEN=ghp_abc", 1_000);
+
+    assert_eq!(
+        cat(&mut osh(home.path())),
+        vec!["export GITHUB_TOKEN=<redacted>"]
+    );
+}
+
+#[test]
+fn files_skips_archives_like_search() {
+    let home = TempDir::new().unwrap();
+    std::fs::create_dir(home.path().join("archive")).unwrap();
+    std::fs::write(home.path().join("archive/local.bosh"), b"").unwrap();
+    let files = |command: &mut Command| {
+        let output = command.arg("files").output().unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert!(!files(&mut osh(home.path())).contains("skipped"));
+
+    std::fs::write(
+        home.path().join("config.toml"),
+        "[retention]\nsearch-archives = false\n",
+    )
+    .unwrap();
+    assert!(files(&mut osh(home.path())).contains("archive/local.bosh\tskipped: archived"));
+}
+
+#[test]
+fn cat_timings_are_printed_to_stderr() {
+    let home = TempDir::new().unwrap();
+    append(&mut osh(home.path()), "ls", 1_000);
+
+    let output = osh(home.path())
+        .args(["cat", "--format", "{command}", "--timings"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ls\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for phase in ["discovery", "cache lookup", "filter", "write"] {
+        assert!(stderr.contains(phase), "{phase} missing in {stderr}");
+    }
+}
+
+#[test]
+fn invalid_events_are_not_appended() {
+    let home = TempDir::new().unwrap();
+    let output = osh(home.path())
+        .args(["append-event", "--command", "ls", "--folder", ""])
+        .args([
+            "--starttime",
+            &NOW.to_string(),
+            "--endtime",
+            &NOW.to_string(),
+        ])
+        .args(["--exit-code", "0", "--machine", "m", "--session", "s"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("folder is empty")
+    );
+    assert!(!home.path().join("local.bosh").exists());
+}